@@ -0,0 +1,743 @@
+//! Compact storage of many small byte strings.
+
+use std::borrow::Cow;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::iter::Peekable;
+use std::mem::size_of;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use crate::or;
+
+const EMPTY: u32 = u32::MAX;
+
+/// Bytes of strings kept in memory before they are written to the spill
+/// files.
+const SPILL_BUFFER: usize = 4 << 20;
+
+/// Bytes of values kept in memory before they are written to the spill file.
+const SPILL_VALUES: usize = 1 << 20;
+
+/// Entries of a sorted run per block, found from the first hash of each.
+const RUN_BLOCK: usize = 256;
+
+/// Bytes of a run entry: the hash, then the id.
+const RUN_ENTRY: usize = 12;
+
+/// Runs of the same level merged into one of the next level.
+const RUN_FANOUT: usize = 4;
+
+/// Bits of the Bloom filter of a run per entry, for about 1% false
+/// positives.
+const BLOOM_BITS: usize = 10;
+const BLOOM_HASHES: u64 = 7;
+
+/// Stores distinct byte strings back to back and assigns them dense ids,
+/// starting from 0. Besides the strings themselves, it uses 8 bytes per
+/// string and a hash table of 4 to 8 bytes per string.
+///
+/// With [`Interner::spill_to`], only the latest strings stay in memory with
+/// their table. The older ones go to files, with their index as sorted runs
+/// of hashes and ids. About 1.3 bytes per string stay in memory, for the
+/// Bloom filters that skip most runs without reading them.
+#[derive(Default)]
+pub struct Interner {
+    // Strings from `hot_start` on, back to back.
+    bytes: Vec<u8>,
+    // Start of each of them in `bytes`. It ends where the next one starts.
+    starts: Vec<u64>,
+    // Open addressing hash table of their ids. The length is a power of 2.
+    table: Vec<u32>,
+    spill: Option<Spill>,
+}
+
+/// Strings of the ids before `hot_start`, in files.
+struct Spill {
+    dir: PathBuf,
+    hot_start: u32,
+    // The strings back to back, and the start of each as 8 bytes.
+    strings: TempFile,
+    strings_len: u64,
+    starts: TempFile,
+    // Oldest first. Levels never increase.
+    runs: Vec<Run>,
+    // Bytes of strings in memory before they are written.
+    buffer: usize,
+}
+
+/// A file of entries sorted by hash, with one for each of some strings.
+struct Run {
+    file: TempFile,
+    len: usize,
+    level: u32,
+    // First hash of each block.
+    firsts: Vec<u64>,
+    bloom: Vec<u64>,
+}
+
+/// A temporary file, removed when dropped. Reading and writing panic on
+/// errors.
+struct TempFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn create(dir: &Path, kind: &str) -> io::Result<Self> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "zachtronics-{}-{}.{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed),
+            kind
+        );
+        let path = dir.join(name);
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self { file, path })
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) {
+        read_at(&self.file, buf, offset).unwrap_or_else(|e| self.fail(e))
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) {
+        write_at(&self.file, buf, offset).unwrap_or_else(|e| self.fail(e))
+    }
+
+    fn fail(&self, e: io::Error) -> ! {
+        panic!("{}: {}", self.path.display(), e)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl Interner {
+    /// Keep older strings in new temporary files in `dir`. The files are
+    /// removed when the interner is dropped.
+    pub fn spill_to(dir: &Path) -> io::Result<Self> {
+        Ok(Self {
+            spill: Some(Spill {
+                dir: dir.to_path_buf(),
+                hot_start: 0,
+                strings: TempFile::create(dir, "strings")?,
+                strings_len: 0,
+                starts: TempFile::create(dir, "starts")?,
+                runs: Vec::new(),
+                buffer: SPILL_BUFFER,
+            }),
+            ..Default::default()
+        })
+    }
+
+    /// Bytes written to the spill files.
+    pub fn spilled_bytes(&self) -> u64 {
+        self.spill.as_ref().map_or(0, |s| {
+            let runs: usize = s.runs.iter().map(|r| r.len * RUN_ENTRY).sum();
+            s.strings_len + s.hot_start as u64 * 8 + runs as u64
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.hot_start() as usize + self.starts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The first id in memory.
+    fn hot_start(&self) -> u32 {
+        self.spill.as_ref().map_or(0, |s| s.hot_start)
+    }
+
+    /// The string with the given id. Read from the spill files if it is
+    /// there. Panics if reading fails.
+    pub fn get(&self, id: u32) -> Cow<'_, [u8]> {
+        let hot_start = self.hot_start();
+        if id >= hot_start {
+            let i = (id - hot_start) as usize;
+            let end = self
+                .starts
+                .get(i + 1)
+                .map_or(self.bytes.len(), |&e| e as usize);
+            return Cow::Borrowed(&self.bytes[self.starts[i] as usize..end]);
+        }
+        let spill = self.spill.as_ref().unwrap();
+        let mut starts = [0; 16];
+        let starts = match id + 1 == hot_start {
+            true => &mut starts[..8],
+            false => &mut starts[..],
+        };
+        spill.starts.read_at(starts, id as u64 * 8);
+        let start = u64::from_le_bytes(starts[..8].try_into().unwrap());
+        let end = match starts.len() {
+            16 => u64::from_le_bytes(starts[8..].try_into().unwrap()),
+            _ => spill.strings_len,
+        };
+        let mut buf = vec![0; (end - start) as usize];
+        spill.strings.read_at(&mut buf, start);
+        Cow::Owned(buf)
+    }
+
+    /// Id of `key` if it was inserted.
+    pub fn find(&self, key: &[u8]) -> Option<u32> {
+        let h = hash(key);
+        if let Some(id) = self.find_hot(key, h) {
+            return Some(id);
+        }
+        let mut found = [None];
+        self.find_spilled(&[key], &[(h, 0)], &mut found);
+        found[0]
+    }
+
+    /// Insert `key` if it is new. Return its id, and whether it is new.
+    pub fn insert(&mut self, key: &[u8]) -> (u32, bool) {
+        match self.spill {
+            Some(_) => self.insert_many(&[key])[0],
+            None => self.insert_hot(key, hash(key)),
+        }
+    }
+
+    /// Insert each of `keys` like [`Interner::insert`]. Looks up the spilled
+    /// strings together, reading each block of a run at most once.
+    pub fn insert_many(&mut self, keys: &[&[u8]]) -> Vec<(u32, bool)> {
+        let hashes: Vec<u64> = keys.iter().map(|key| hash(key)).collect();
+        let mut found: Vec<Option<u32>> = (0..keys.len())
+            .map(|i| self.find_hot(keys[i], hashes[i]))
+            .collect();
+        let mut cold: Vec<(u64, usize)> = (0..keys.len())
+            .filter(|&i| found[i].is_none())
+            .map(|i| (hashes[i], i))
+            .collect();
+        cold.sort_unstable();
+        self.find_spilled(keys, &cold, &mut found);
+        let result = (0..keys.len())
+            .map(|i| match found[i] {
+                Some(id) => (id, false),
+                // Also finds a key inserted earlier in the batch.
+                None => self.insert_hot(keys[i], hashes[i]),
+            })
+            .collect();
+        if self
+            .spill
+            .as_ref()
+            .is_some_and(|s| self.bytes.len() >= s.buffer)
+        {
+            self.write_hot();
+        }
+        result
+    }
+
+    /// Approximate memory used, in bytes. Does not count the spill files.
+    pub fn memory_usage(&self) -> usize {
+        let runs = self.spill.as_ref().map_or(0, |s| {
+            let run = |r: &Run| (r.firsts.capacity() + r.bloom.capacity()) * size_of::<u64>();
+            s.runs.iter().map(run).sum()
+        });
+        self.bytes.capacity()
+            + self.starts.capacity() * size_of::<u64>()
+            + self.table.capacity() * size_of::<u32>()
+            + runs
+    }
+
+    fn find_hot(&self, key: &[u8], h: u64) -> Option<u32> {
+        if self.table.is_empty() {
+            return None;
+        }
+        match self.table[self.slot(key, h)] {
+            EMPTY => None,
+            id => Some(id),
+        }
+    }
+
+    /// Set `found[i]` to the id of `keys[i]`, for each `(hash, i)` of `cold`
+    /// that is in a run. `cold` is sorted.
+    fn find_spilled(&self, keys: &[&[u8]], cold: &[(u64, usize)], found: &mut [Option<u32>]) {
+        let spill = or!(&self.spill, return);
+        let mut block = Vec::new();
+        for run in &spill.runs {
+            let mut block_index = usize::MAX;
+            for &(h, i) in cold {
+                if found[i].is_some() || !run.may_contain(h) {
+                    continue;
+                }
+                // Entries with this hash start in the last block starting
+                // before it, and may go on in the next blocks.
+                let first = run.firsts.partition_point(|&f| f < h).saturating_sub(1);
+                for b in first..run.firsts.len() {
+                    if run.firsts[b] > h {
+                        break;
+                    }
+                    if b != block_index {
+                        run.read_block(b, &mut block);
+                        block_index = b;
+                    }
+                    let mut ids = block.chunks(RUN_ENTRY).filter_map(|entry| {
+                        let (eh, id) = entry.split_at(8);
+                        (u64::from_le_bytes(eh.try_into().unwrap()) == h)
+                            .then(|| u32::from_le_bytes(id.try_into().unwrap()))
+                    });
+                    if let Some(id) = ids.find(|&id| *self.get(id) == *keys[i]) {
+                        found[i] = Some(id);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn insert_hot(&mut self, key: &[u8], h: u64) -> (u32, bool) {
+        if (self.starts.len() + 1) * 4 > self.table.len() * 3 {
+            self.grow();
+        }
+        let slot = self.slot(key, h);
+        if self.table[slot] != EMPTY {
+            return (self.table[slot], false);
+        }
+        let id = u32::try_from(self.len()).expect("too many strings");
+        assert_ne!(id, EMPTY, "too many strings");
+        self.starts.push(self.bytes.len() as u64);
+        self.bytes.extend_from_slice(key);
+        self.table[slot] = id;
+        (id, true)
+    }
+
+    /// Move the strings in memory to the spill files, and their index to a
+    /// new run. Then merge the last runs while they have the same level.
+    fn write_hot(&mut self) {
+        let mut entries: Vec<(u64, u32)> = (0..self.starts.len() as u32)
+            .map(|i| {
+                let id = self.hot_start() + i;
+                (hash(&self.get(id)), id)
+            })
+            .collect();
+        entries.sort_unstable();
+        let spill = self.spill.as_mut().unwrap();
+        let starts: Vec<u8> = self
+            .starts
+            .iter()
+            .flat_map(|&s| (spill.strings_len + s).to_le_bytes())
+            .collect();
+        spill.starts.write_at(&starts, spill.hot_start as u64 * 8);
+        spill.strings.write_at(&self.bytes, spill.strings_len);
+        spill.strings_len += self.bytes.len() as u64;
+        spill.hot_start += self.starts.len() as u32;
+        let run = Run::write(&spill.dir, 0, entries.len(), entries.into_iter());
+        spill
+            .runs
+            .push(run.unwrap_or_else(|e| panic!("{}: {}", spill.dir.display(), e)));
+        while let Some(start) = spill.runs.len().checked_sub(RUN_FANOUT) {
+            if spill.runs[start..]
+                .iter()
+                .any(|r| r.level != spill.runs[start].level)
+            {
+                break;
+            }
+            let merged = spill.runs.split_off(start);
+            let run = Run::merge(&spill.dir, &merged);
+            spill
+                .runs
+                .push(run.unwrap_or_else(|e| panic!("{}: {}", spill.dir.display(), e)));
+        }
+        self.bytes.clear();
+        self.starts.clear();
+        self.table.fill(EMPTY);
+    }
+
+    /// Slot of `key` with hash `h` in the table, or the empty slot to insert
+    /// it at.
+    fn slot(&self, key: &[u8], h: u64) -> usize {
+        let mask = self.table.len() - 1;
+        let mut slot = h as usize & mask;
+        loop {
+            let id = self.table[slot];
+            if id == EMPTY || *self.get(id) == *key {
+                return slot;
+            }
+            slot = (slot + 1) & mask;
+        }
+    }
+
+    fn grow(&mut self) {
+        let len = (self.table.len() * 2).max(1024);
+        assert!(len <= 1 << 32, "too many strings");
+        self.table = vec![EMPTY; len];
+        for id in self.hot_start()..self.len() as u32 {
+            let mut slot = hash(&self.get(id)) as usize & (len - 1);
+            while self.table[slot] != EMPTY {
+                slot = (slot + 1) & (len - 1);
+            }
+            self.table[slot] = id;
+        }
+    }
+}
+
+impl Run {
+    /// Write `len` entries, sorted by hash, to a new file in `dir`.
+    fn write(
+        dir: &Path,
+        level: u32,
+        len: usize,
+        entries: impl Iterator<Item = (u64, u32)>,
+    ) -> io::Result<Run> {
+        let file = TempFile::create(dir, "run")?;
+        let mut run = Run {
+            file,
+            len,
+            level,
+            firsts: Vec::with_capacity(len.div_ceil(RUN_BLOCK)),
+            bloom: vec![0; (len * BLOOM_BITS).div_ceil(64).max(1)],
+        };
+        let mut out = BufWriter::new(&run.file.file);
+        for (i, (h, id)) in entries.enumerate() {
+            if i % RUN_BLOCK == 0 {
+                run.firsts.push(h);
+            }
+            for bit in bloom_bits(h, run.bloom.len() * 64) {
+                run.bloom[bit / 64] |= 1 << (bit % 64);
+            }
+            out.write_all(&h.to_le_bytes())?;
+            out.write_all(&id.to_le_bytes())?;
+        }
+        out.flush()?;
+        drop(out);
+        Ok(run)
+    }
+
+    /// Merge runs into a new one of the next level.
+    fn merge(dir: &Path, runs: &[Run]) -> io::Result<Run> {
+        let mut readers: Vec<Peekable<_>> = runs.iter().map(|r| r.entries().peekable()).collect();
+        let entries = std::iter::from_fn(|| {
+            let next = readers
+                .iter_mut()
+                .enumerate()
+                .filter_map(|(i, r)| Some((*r.peek()?, i)))
+                .min()?;
+            readers[next.1].next()
+        });
+        let len = runs.iter().map(|r| r.len).sum();
+        Run::write(dir, runs[0].level + 1, len, entries)
+    }
+
+    /// Read the entries in order, a block at a time.
+    fn entries(&self) -> impl Iterator<Item = (u64, u32)> + '_ {
+        let mut block = Vec::new();
+        (0..self.len).map(move |i| {
+            if i % RUN_BLOCK == 0 {
+                self.read_block(i / RUN_BLOCK, &mut block);
+            }
+            let entry = &block[i % RUN_BLOCK * RUN_ENTRY..][..RUN_ENTRY];
+            let (h, id) = entry.split_at(8);
+            (
+                u64::from_le_bytes(h.try_into().unwrap()),
+                u32::from_le_bytes(id.try_into().unwrap()),
+            )
+        })
+    }
+
+    fn may_contain(&self, h: u64) -> bool {
+        bloom_bits(h, self.bloom.len() * 64)
+            .all(|bit| self.bloom[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    fn read_block(&self, b: usize, buf: &mut Vec<u8>) {
+        let len = RUN_BLOCK.min(self.len - b * RUN_BLOCK);
+        buf.resize(len * RUN_ENTRY, 0);
+        self.file.read_at(buf, (b * RUN_BLOCK * RUN_ENTRY) as u64);
+    }
+}
+
+/// Bits of a Bloom filter of `len` bits for the hash `h`.
+fn bloom_bits(h: u64, len: usize) -> impl Iterator<Item = usize> {
+    let step = h.rotate_left(32) | 1;
+    (0..BLOOM_HASHES).map(move |i| (h.wrapping_add(i.wrapping_mul(step)) % len as u64) as usize)
+}
+
+/// A number stored in a fixed number of bytes.
+pub trait Fixed: Copy + Default {
+    const SIZE: usize;
+    fn to_bytes(self, out: &mut Vec<u8>);
+    fn from_bytes(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_fixed {
+    ($($t:ty),*) => {$(
+        impl Fixed for $t {
+            const SIZE: usize = size_of::<$t>();
+            fn to_bytes(self, out: &mut Vec<u8>) {
+                out.extend(self.to_le_bytes());
+            }
+            fn from_bytes(bytes: &[u8]) -> Self {
+                Self::from_le_bytes(bytes.try_into().unwrap())
+            }
+        }
+    )*};
+}
+
+impl_fixed!(u16, u32);
+
+/// A vector of numbers. With [`SpillVec::spill_to`], all but the latest
+/// values are kept in a file.
+#[derive(Default)]
+pub struct SpillVec<T> {
+    // Values from `spilled` on.
+    values: Vec<T>,
+    spilled: usize,
+    file: Option<TempFile>,
+    // Values in memory before they are written.
+    buffer: usize,
+}
+
+impl<T: Fixed> SpillVec<T> {
+    /// Keep older values in a new temporary file in `dir`. The file is
+    /// removed when the vector is dropped.
+    pub fn spill_to(dir: &Path) -> io::Result<Self> {
+        Ok(Self {
+            file: Some(TempFile::create(dir, "values")?),
+            buffer: SPILL_VALUES / T::SIZE,
+            ..Default::default()
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.spilled + self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Panics if `i` is out of bounds or reading fails.
+    pub fn get(&self, i: usize) -> T {
+        if i >= self.spilled {
+            return self.values[i - self.spilled];
+        }
+        let mut buf = [0; 8];
+        let buf = &mut buf[..T::SIZE];
+        self.file
+            .as_ref()
+            .unwrap()
+            .read_at(buf, (i * T::SIZE) as u64);
+        T::from_bytes(buf)
+    }
+
+    /// Panics if `i` is out of bounds or writing fails.
+    pub fn set(&mut self, i: usize, value: T) {
+        if i >= self.spilled {
+            self.values[i - self.spilled] = value;
+            return;
+        }
+        let mut buf = Vec::with_capacity(T::SIZE);
+        value.to_bytes(&mut buf);
+        self.file
+            .as_ref()
+            .unwrap()
+            .write_at(&buf, (i * T::SIZE) as u64);
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.values.push(value);
+        if let Some(file) = &self.file {
+            if self.values.len() >= self.buffer {
+                let mut buf = Vec::with_capacity(self.values.len() * T::SIZE);
+                for &v in &self.values {
+                    v.to_bytes(&mut buf);
+                }
+                file.write_at(&buf, (self.spilled * T::SIZE) as u64);
+                self.spilled += self.values.len();
+                self.values.clear();
+            }
+        }
+    }
+
+    /// Approximate memory used, in bytes. Does not count the spill file.
+    pub fn memory_usage(&self) -> usize {
+        self.values.capacity() * size_of::<T>()
+    }
+}
+
+/// A set of small integers, one bit each.
+#[derive(Default)]
+pub struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    pub fn contains(&self, i: usize) -> bool {
+        self.words
+            .get(i / 64)
+            .is_some_and(|w| w & (1 << (i % 64)) != 0)
+    }
+
+    /// Return whether `i` was not in the set.
+    pub fn insert(&mut self, i: usize) -> bool {
+        if i / 64 >= self.words.len() {
+            self.words.resize(i / 64 + 1, 0);
+        }
+        let was_set = self.contains(i);
+        self.words[i / 64] |= 1 << (i % 64);
+        !was_set
+    }
+
+    pub fn remove(&mut self, i: usize) {
+        if let Some(w) = self.words.get_mut(i / 64) {
+            *w &= !(1 << (i % 64));
+        }
+    }
+
+    /// Approximate memory used, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.words.capacity() * size_of::<u64>()
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn write_at(file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn write_at(file: &File, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_write(buf, offset)? {
+            0 => return Err(io::ErrorKind::WriteZero.into()),
+            n => {
+                buf = &buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A fast, non-cryptographic hash.
+fn hash(bytes: &[u8]) -> u64 {
+    const K: u64 = 0x517c_c1b7_2722_0a95;
+    let mut h = bytes.len() as u64;
+    for chunk in bytes.chunks(8) {
+        let mut word = [0u8; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        h = (h.rotate_left(5) ^ u64::from_le_bytes(word)).wrapping_mul(K);
+    }
+    // Mix high bits into the low bits used by the table.
+    h ^ (h >> 29)
+}
+
+#[test]
+fn test_interner() {
+    let mut interner = Interner::default();
+    assert_eq!(interner.find(b"a"), None);
+    assert_eq!(interner.insert(b"a"), (0, true));
+    assert_eq!(interner.insert(b""), (1, true));
+    assert_eq!(interner.insert(b"a"), (0, false));
+    for i in 0..5000u32 {
+        interner.insert(&i.to_le_bytes());
+    }
+    assert_eq!(interner.len(), 5002);
+    assert_eq!(interner.find(&1234u32.to_le_bytes()), Some(1236));
+    assert_eq!(*interner.get(1236), 1234u32.to_le_bytes());
+    assert_eq!(*interner.get(1), *b"");
+    assert_eq!(interner.find(b"a"), Some(0));
+}
+
+#[test]
+fn test_spill() {
+    let dir = std::env::temp_dir();
+    let mut interner = Interner::spill_to(&dir).unwrap();
+    interner.spill.as_mut().unwrap().buffer = 1000;
+    let key = |i: u32| i.to_le_bytes().repeat(1 + i as usize % 10);
+    for i in 0..40_000 {
+        assert_eq!(interner.insert(&key(i)), (i, true));
+    }
+    let spill = interner.spill.as_ref().unwrap();
+    let paths: Vec<PathBuf> = [&spill.strings, &spill.starts]
+        .into_iter()
+        .chain(spill.runs.iter().map(|r| &r.file))
+        .map(|f| f.path.clone())
+        .collect();
+    assert!(spill.runs.iter().any(|r| r.level > 1));
+    assert!(spill.runs.len() < 20);
+    assert_eq!(interner.insert(&key(5)), (5, false));
+    assert_eq!(interner.find(&key(30_000)), Some(30_000));
+    assert_eq!(interner.find(&key(40_000)), None);
+    assert_eq!(*interner.get(7), *key(7));
+    assert_eq!(*interner.get(39_999), *key(39_999));
+    let keys = [key(3), key(40_001), key(39_998), key(40_001), key(12_345)];
+    let keys: Vec<&[u8]> = keys.iter().map(|k| &k[..]).collect();
+    assert_eq!(
+        interner.insert_many(&keys),
+        [
+            (3, false),
+            (40_000, true),
+            (39_998, false),
+            (40_000, false),
+            (12_345, false)
+        ]
+    );
+    for i in (0..40_001).step_by(97) {
+        assert_eq!(interner.find(&key(i)), Some(i));
+        assert_eq!(*interner.get(i), *key(i));
+    }
+    drop(interner);
+    assert!(paths.iter().all(|p| !p.exists()));
+
+    let mut values = SpillVec::spill_to(&dir).unwrap();
+    values.buffer = 100;
+    for i in 0..1000u32 {
+        values.push(i * 2);
+    }
+    values.set(10, 7);
+    values.set(999, 8);
+    assert_eq!(
+        (values.len(), values.get(10), values.get(11)),
+        (1000, 7, 22)
+    );
+    assert_eq!(values.get(999), 8);
+}
+
+#[test]
+fn test_bit_set() {
+    let mut set = BitSet::default();
+    assert!(!set.contains(100));
+    assert!(set.insert(100));
+    assert!(!set.insert(100));
+    assert!(set.contains(100));
+    assert!(!set.contains(99));
+    set.remove(100);
+    assert!(!set.contains(100));
+}
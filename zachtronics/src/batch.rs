@@ -0,0 +1,397 @@
+//! Solve many puzzles in parallel and summarize the results.
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use zachtronics::json::Json;
+use zachtronics::limits::GaveUp;
+use zachtronics::limits::Limits;
+
+use crate::cli::Flag;
+use crate::cli::Matches;
+use crate::registry;
+use crate::registry::Game;
+
+pub const FLAGS: &[Flag] = &[
+    Flag {
+        name: "--format",
+        short: Some("-f"),
+        value: Some("table|csv|json"),
+        env: None,
+        help: "Output format of the summary",
+    },
+    JOBS_FLAG,
+];
+
+/// Flag of the commands that solve in parallel. Read it with `jobs`.
+pub const JOBS_FLAG: Flag = Flag {
+    name: "--jobs",
+    short: Some("-j"),
+    value: Some("N"),
+    env: None,
+    help: "Number of searches to run in parallel. Default: number of CPUs",
+};
+
+/// Result of solving a puzzle file.
+struct Row {
+    path: String,
+    result: Result<Summary, String>,
+    time: Duration,
+}
+
+struct Summary {
+    solved: bool,
+    unsolvable: bool,
+    gave_up: Option<GaveUp>,
+    score: Option<usize>,
+    step_count: Option<usize>,
+    searched: usize,
+    cache_hits: usize,
+}
+
+impl Row {
+    fn is_solved(&self) -> bool {
+        matches!(self.result, Ok(Summary { solved: true, .. }))
+    }
+
+    fn status(&self) -> &'static str {
+        match &self.result {
+            Ok(s) if s.solved => "solved",
+            Ok(s) if s.unsolvable => "unsolvable",
+            Ok(Summary {
+                gave_up: Some(_), ..
+            }) => "gave up",
+            Ok(_) => "unsolved",
+            Err(_) => "error",
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        let mut v = Json::object([
+            ("file", Json::from(self.path.as_str())),
+            ("result", self.status().into()),
+        ]);
+        match &self.result {
+            Ok(s) => {
+                v.push("gave_up", s.gave_up.map(|r| r.name()).into());
+                v.push("score", s.score.into());
+                v.push("steps", s.step_count.into());
+                v.push("searched", s.searched.into());
+                v.push("cache_hits", s.cache_hits.into());
+            }
+            Err(e) => v.push("error", e.as_str().into()),
+        }
+        v.push("seconds", self.time.as_secs_f64().into());
+        v
+    }
+}
+
+/// Run the batch command. `m.positional` are paths.
+pub fn run(game: &Game, m: &Matches) -> Result<(), String> {
+    let format = m.value("--format").unwrap_or("table");
+    if !["table", "csv", "json"].contains(&format) {
+        return Err(format!("unsupported format: {}", format));
+    }
+    let jobs = jobs(m)?;
+    if m.positional.is_empty() {
+        return Err("no puzzle files given".to_string());
+    }
+    let limits = registry::limits(m)?;
+    let paths = expand_paths(&m.positional)?;
+    let rows = solve_files(game, m, &limits, &paths, jobs);
+    let out = match format {
+        "csv" => render_csv(&rows),
+        "json" => format!(
+            "{}\n",
+            Json::from(rows.iter().map(Row::to_json).collect::<Vec<_>>())
+        ),
+        _ => render_table(&rows),
+    };
+    print!("{}", out);
+
+    let failed = rows.iter().filter(|r| !r.is_solved()).count();
+    if failed > 0 {
+        return Err(format!("{} of {} puzzles not solved", failed, rows.len()));
+    }
+    Ok(())
+}
+
+fn solve_files(
+    game: &Game,
+    m: &Matches,
+    limits: &Limits,
+    paths: &[PathBuf],
+    jobs: usize,
+) -> Vec<Row> {
+    run_parallel(jobs, paths.len(), |i| {
+        solve_file(game, m, limits, &paths[i])
+    })
+}
+
+/// Number of parallel searches given by `--jobs`.
+pub fn jobs(m: &Matches) -> Result<usize, String> {
+    Ok(match m.parse_value::<usize>("--jobs")? {
+        Some(n) => n.max(1),
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    })
+}
+
+/// Call `f` on `0..n` from `jobs` threads. Return the results in order.
+pub fn run_parallel<T: Send>(jobs: usize, n: usize, f: impl Fn(usize) -> T + Sync) -> Vec<T> {
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<T>>> = (0..n).map(|_| Mutex::new(None)).collect();
+    thread::scope(|s| {
+        for _ in 0..jobs.min(n) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= n {
+                    break;
+                }
+                let result = f(i);
+                *results[i].lock().unwrap() = Some(result);
+            });
+        }
+    });
+    results
+        .into_iter()
+        .map(|r| r.into_inner().unwrap().unwrap())
+        .collect()
+}
+
+fn solve_file(game: &Game, m: &Matches, limits: &Limits, path: &Path) -> Row {
+    let start = Instant::now();
+    let result = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|input| (game.solve)(&input, m, limits))
+        .map(|solution| {
+            let stats = solution.stats();
+            Summary {
+                solved: solution.is_solved(),
+                unsolvable: solution.is_unsolvable(),
+                gave_up: solution.gave_up(),
+                score: solution.score(),
+                step_count: solution.step_count(),
+                searched: stats.searched,
+                cache_hits: stats.cache_hits,
+            }
+        });
+    Row {
+        path: path.display().to_string(),
+        result,
+        time: start.elapsed(),
+    }
+}
+
+fn render_table(rows: &[Row]) -> String {
+    let opt = |v: Option<usize>| v.map_or("-".to_string(), |v| v.to_string());
+    let mut lines = vec![[
+        "File",
+        "Result",
+        "Score",
+        "Steps",
+        "Searched",
+        "Cache hits",
+        "Time",
+    ]
+    .map(String::from)];
+    for row in rows {
+        let (score, steps, searched, cache_hits) = match &row.result {
+            Ok(s) => (
+                opt(s.score),
+                opt(s.step_count),
+                s.searched.to_string(),
+                s.cache_hits.to_string(),
+            ),
+            Err(_) => Default::default(),
+        };
+        let time = format!("{:.3}s", row.time.as_secs_f64());
+        lines.push([
+            row.path.clone(),
+            row.status().to_string(),
+            score,
+            steps,
+            searched,
+            cache_hits,
+            time,
+        ]);
+    }
+    let mut widths = [0; 7];
+    for line in &lines {
+        for (w, cell) in widths.iter_mut().zip(line) {
+            *w = (*w).max(cell.len());
+        }
+    }
+    let mut out = String::new();
+    for line in &lines {
+        let mut cells = Vec::new();
+        for (i, (cell, width)) in line.iter().zip(widths).enumerate() {
+            if i < 2 {
+                cells.push(format!("{:<width$}", cell));
+            } else {
+                cells.push(format!("{:>width$}", cell));
+            }
+        }
+        out += cells.join("  ").trim_end();
+        out.push('\n');
+    }
+    for row in rows {
+        if let Err(e) = &row.result {
+            out += &format!("{}: {}\n", row.path, e);
+        }
+    }
+    let solved = rows.iter().filter(|r| r.is_solved()).count();
+    out += &format!("Solved {}/{}.\n", solved, rows.len());
+    out
+}
+
+fn render_csv(rows: &[Row]) -> String {
+    let mut out = String::from("file,result,score,steps,searched,cache_hits,seconds,error\n");
+    let opt = |v: Option<usize>| v.map_or(String::new(), |v| v.to_string());
+    for row in rows {
+        let cells = match &row.result {
+            Ok(s) => [
+                opt(s.score),
+                opt(s.step_count),
+                s.searched.to_string(),
+                s.cache_hits.to_string(),
+                String::new(),
+            ],
+            Err(e) => [
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                e.clone(),
+            ],
+        };
+        let [score, steps, searched, cache_hits, error] = cells;
+        out += &format!(
+            "{},{},{},{},{},{},{:.6},{}\n",
+            csv_escape(&row.path),
+            row.status(),
+            score,
+            steps,
+            searched,
+            cache_hits,
+            row.time.as_secs_f64(),
+            csv_escape(&error),
+        );
+    }
+    out
+}
+
+fn csv_escape(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Expand directories (recursively) and globs in file names.
+pub fn expand_paths(args: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut result = Vec::new();
+    for arg in args {
+        let path = Path::new(arg);
+        if arg.contains(['*', '?']) {
+            let dir = path.parent().filter(|p| !p.as_os_str().is_empty());
+            let dir = dir.unwrap_or(Path::new("."));
+            if dir.to_string_lossy().contains(['*', '?']) {
+                return Err(format!("globs are only supported in file names: {}", arg));
+            }
+            let pattern = path.file_name().unwrap_or_default().to_string_lossy();
+            let mut matched = list_dir(dir)?
+                .into_iter()
+                .filter(|p| p.is_file())
+                .filter(|p| {
+                    let name = p.file_name().unwrap_or_default().to_string_lossy();
+                    wildcard_match(&pattern, &name)
+                })
+                .collect::<Vec<_>>();
+            if matched.is_empty() {
+                return Err(format!("no files match {}", arg));
+            }
+            result.append(&mut matched);
+        } else if path.is_dir() {
+            let mut to_visit = vec![path.to_path_buf()];
+            let mut files = Vec::new();
+            while let Some(dir) = to_visit.pop() {
+                for p in list_dir(&dir)? {
+                    if p.is_dir() {
+                        to_visit.push(p);
+                    } else {
+                        files.push(p);
+                    }
+                }
+            }
+            files.sort();
+            result.append(&mut files);
+        } else if path.is_file() {
+            result.push(path.to_path_buf());
+        } else {
+            return Err(format!("{}: not found", arg));
+        }
+    }
+    Ok(result)
+}
+
+fn list_dir(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let mut paths = entries
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    paths.sort();
+    Ok(paths)
+}
+
+/// Match `*` (any string) and `?` (any char).
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut i, mut j) = (0, 0);
+    // Position after the last '*' in the pattern, and the matching position in name.
+    let mut star: Option<(usize, usize)> = None;
+    while j < n.len() {
+        if i < p.len() && (p[i] == '?' || p[i] == n[j]) {
+            i += 1;
+            j += 1;
+        } else if i < p.len() && p[i] == '*' {
+            star = Some((i + 1, j));
+            i += 1;
+        } else if let Some((si, sj)) = star {
+            i = si;
+            j = sj + 1;
+            star = Some((si, sj + 1));
+        } else {
+            return false;
+        }
+    }
+    p[i..].iter().all(|&c| c == '*')
+}
+
+#[test]
+fn test_wildcard_match() {
+    assert!(wildcard_match("*.in", "1.in"));
+    assert!(wildcard_match("*", ""));
+    assert!(wildcard_match("a?c*", "abcde"));
+    assert!(wildcard_match("*b*b", "abcbb"));
+    assert!(!wildcard_match("*.in", "1.out"));
+    assert!(!wildcard_match("a?c", "ac"));
+}
+
+#[test]
+fn test_run_parallel() {
+    assert_eq!(
+        run_parallel(3, 10, |i| i * i),
+        [0, 1, 4, 9, 16, 25, 36, 49, 64, 81]
+    );
+    assert_eq!(run_parallel(4, 0, |i| i), Vec::<usize>::new());
+}
@@ -11,32 +11,113 @@
 //! t  d k  8 6  v
 //!
 //! Spaces and '1' are optional.
+//!
+//! A mid-game position lists one column per line instead, from the bottom
+//! card to the top, and the card in the free slot after '|':
+//!
+//! 1: T 9 8 T 8 | 9
+//! 2: T K D V 10 9 8 7 6
+//! ...
+//!
+//! Columns that are not listed are empty.
 
-use crate::dprintln;
+use crate::arena::BitSet;
+use crate::arena::Interner;
+use crate::arena::SpillVec;
+use crate::debug;
+use crate::info;
+use crate::json::Json;
+use crate::limits::Budget;
+use crate::limits::CancelHandle;
+use crate::limits::GaveUp;
+use crate::limits::Limits;
+use crate::log;
+use crate::log::Level;
 use crate::or;
+use crate::trace;
 use crate::util::is_verbose;
 use crate::util::NVec;
+use crate::util::Rng;
+use crate::GameInfo;
+use crate::ParseError;
+use crate::Stats;
 
 use std::collections::BinaryHeap;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::fmt;
 use std::fmt::Write;
 use std::hash::Hash;
+use std::io;
+use std::mem::size_of;
 use std::ops::Add;
+use std::ops::ControlFlow;
 use std::ops::Sub;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
 #[derive(Copy, Clone, Hash, Default, PartialEq, Eq, PartialOrd, Ord)]
 struct Span(Card, u8);
 
+/// A card. `Card(0)` is "2", `Card(12)` is "T". The standard deck is "6"
+/// to "T".
 #[derive(Copy, Clone, Hash, Default, PartialEq, Eq, PartialOrd, Ord)]
-struct Card(u8);
+pub struct Card(u8);
 
+/// Spans of cards (bottom to top), the free slot, and whether the column is
+/// complete.
 #[derive(Copy, Clone, Hash, Default, PartialEq, Eq, PartialOrd, Ord)]
-struct Column(NVec<Span, 6>, Option<Card>);
+pub struct Column(NVec<Span, 6>, Option<Card>, bool);
+
+const MAX_COLUMNS: usize = 8;
 
+/// The board. 6 columns with the standard rules.
 #[derive(Copy, Clone, Hash, Default, PartialEq, Eq, PartialOrd, Ord)]
-struct State([Column; 6]);
+pub struct State(NVec<Column, MAX_COLUMNS>);
+
+/// Rules of a variant of the game. The default is the standard game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rules {
+    /// Number of columns, 1 to 8.
+    pub columns: u8,
+    /// Lowest and highest rank of the deck.
+    pub lowest: Card,
+    pub highest: Card,
+    /// Cards of each rank.
+    pub copies: u8,
+    /// Whether a card can be put in the free slot of a column.
+    pub slots: bool,
+    /// A column is complete, and can no longer change, once it is a single
+    /// run of this many cards. The deal is solved when all columns are
+    /// complete or empty.
+    pub run_len: u8,
+}
+
+/// Weights of the score that orders the default (not optimal) search.
+/// Higher scores are searched first. Scores saturate at 255 for weights that
+/// do not pass [`Heuristic::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Heuristic {
+    /// Per span fewer than 6 in a column.
+    pub tidy: u8,
+    /// Per empty free slot.
+    pub free_slot: u8,
+    /// Per empty column.
+    pub empty_column: u8,
+    /// Per complete column.
+    pub complete: u8,
+    /// Penalty per card of the longest span that does not fit in the empty
+    /// slots. Only with slots.
+    pub stuck: u8,
+}
 
 /// How to transfer one state to another State.
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
@@ -50,22 +131,25 @@ struct TransferStep {
 }
 
 impl TransferStep {
-    fn explain(&self, state: &State) -> String {
+    fn explain(&self, state: &State) -> Move {
         let (col1, col2) = self.find_col1_col2(state);
-        let cards = state.0[col1 as usize].explain_last_n_cards(self.card_count);
-        format!(
-            "Move {} from {} -> {}. {} to {}.",
+        let cards = state.0[col1].last_n_cards(self.card_count);
+        Move {
+            from: col1,
+            to: col2,
             cards,
-            col1 + 1,
-            col2 + 1,
-            self.from_column,
-            self.to_column
-        )
+            to_slot: self.to_slot,
+            from_column: self.from_column,
+            to_column: self.to_column,
+            board: *state,
+            board_score: 0,
+        }
     }
 
-    fn apply(&self, state: &mut State) {
+    fn apply(&self, state: &mut State, rules: &Rules) {
         let (col1, col2) = self.find_col1_col2(state);
         state.unchecked_apply_moving_to(col1, col2, self.card_count, self.to_slot, 0);
+        state.mark_complete(rules);
     }
 
     fn find_col1_col2(&self, state: &State) -> (u8, u8) {
@@ -75,12 +159,784 @@ impl TransferStep {
     }
 }
 
-const CARD_STRS: [&str; 9] = ["6", "7", "8", "9", "10", "V", "D", "K", "T"];
+/// A move in a solution.
+#[derive(Clone, Debug)]
+pub struct Move {
+    /// Column the cards are taken from. 0-based.
+    pub from: u8,
+    /// Column the cards are moved to. 0-based.
+    pub to: u8,
+    /// Moved cards, from the highest rank to the lowest.
+    pub cards: Vec<Card>,
+    /// Whether the card is put into the free slot of the target column.
+    pub to_slot: bool,
+
+    // For "explain" use-case.
+    from_column: Column,
+    to_column: Column,
+    // Board after the move, and its score.
+    board: State,
+    board_score: u8,
+}
+
+/// Search options.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Find a solution with the fewest moves (A* search). Slower.
+    pub optimal: bool,
+    /// Threads to expand states on. 0 and 1 are single threaded.
+    pub threads: usize,
+    /// With more than one thread, find the same solution as a single thread.
+    /// Slower. Without it, `optimal` still finds the fewest moves, but maybe
+    /// not the same ones.
+    pub deterministic: bool,
+    /// Keep searching after a solution is found, to count all reachable
+    /// states.
+    pub exhaustive: bool,
+    /// Must be the rules the state was parsed with.
+    pub rules: Rules,
+    /// Orders the search without `optimal`.
+    pub heuristic: Heuristic,
+    /// Time to spend shortening a solution found without `optimal`.
+    pub shorten: Option<Duration>,
+    /// Keep the searched states, their parents and their index in temporary
+    /// files in this directory instead of memory. Slower, but fits more
+    /// states.
+    pub spill_dir: Option<PathBuf>,
+    /// Stop the search without `optimal` at a position of this tablebase,
+    /// and finish with its line. Must be built for `rules`.
+    pub tablebase: Option<Arc<Tablebase>>,
+    /// Search the states that a [`Deadlock`] detector finds lost, instead
+    /// of pruning them. Always on for `exhaustive`.
+    pub keep_deadlocks: bool,
+    /// Names of the face cards in the text output.
+    pub notation: Notation,
+}
+
+/// Result of [`State::solve`](crate::Solver::solve).
+#[derive(Clone, Debug)]
+pub struct Solution {
+    /// Moves to solve the deal. `None` if no solution was found.
+    pub moves: Option<Vec<Move>>,
+    /// Whether no solution has fewer moves.
+    pub proven_optimal: bool,
+    /// Set if the search stopped early.
+    pub gave_up: Option<GaveUp>,
+    /// Whether all reachable states were searched without finding a
+    /// solution, proving there is none.
+    pub unsolvable: bool,
+    /// Number of reachable states, if all of them were searched.
+    pub reachable: Option<usize>,
+    /// If the search gave up or the deal is unsolvable, moves to the best
+    /// state reached.
+    pub partial_moves: Vec<Move>,
+    /// Step count before the solution was shortened, if it was.
+    pub shortened_from: Option<usize>,
+    /// States pruned by each deadlock detector.
+    pub pruned: Vec<(Deadlock, usize)>,
+    pub stats: Stats,
+    /// Names of the face cards in the text output. JSON always uses
+    /// [`Notation::Latin`].
+    pub notation: Notation,
+}
+
+impl fmt::Display for Notated<'_, Move> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Notated(m, notation) = *self;
+        let cards: Vec<&str> = m.cards.iter().map(|c| c.name(notation)).collect();
+        write!(
+            f,
+            "Move [{}] from {} -> {}. {} to {}.",
+            cards.join(" "),
+            m.from + 1,
+            m.to + 1,
+            Notated(&m.from_column, notation),
+            Notated(&m.to_column, notation)
+        )
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Notated(self, Notation::default()).fmt(f)
+    }
+}
+
+impl fmt::Display for Solution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let moves = match (&self.moves, self.gave_up) {
+            (Some(moves), _) => moves,
+            (None, Some(reason)) => {
+                writeln!(f, "Gave up ({}). Best progress:", reason)?;
+                &self.partial_moves
+            }
+            (None, None) if self.unsolvable && self.partial_moves.is_empty() => {
+                writeln!(f, "No solution exists. No move is possible.")?;
+                &self.partial_moves
+            }
+            (None, None) if self.unsolvable => {
+                writeln!(f, "No solution exists. Best progress:")?;
+                &self.partial_moves
+            }
+            (None, None) => return writeln!(f, "No solution found."),
+        };
+        let notation = self.notation;
+        for (i, m) in moves.iter().enumerate() {
+            writeln!(f, "Step {:>3}. {}", i + 1, Notated(m, notation))?;
+            if is_verbose() {
+                let board = Notated(&m.board, notation);
+                writeln!(f, "          Board: {} (Score: {})", board, m.board_score)?;
+            }
+        }
+        if self.moves.is_some() {
+            let proven = if self.proven_optimal {
+                "proven"
+            } else {
+                "not proven"
+            };
+            writeln!(f, "Solved in {} steps ({} optimal).", moves.len(), proven)?;
+            if let Some(n) = self.shortened_from {
+                writeln!(f, "Shortened from {} to {} steps.", n, moves.len())?;
+            }
+            if let Some(reason) = self.gave_up {
+                writeln!(
+                    f,
+                    "Gave up ({}) before searching all reachable states.",
+                    reason
+                )?;
+            }
+        } else if self.unsolvable {
+            let board = self
+                .partial_moves
+                .last()
+                .map(|m| Notated(&m.board, notation));
+            if let Some(board) = board {
+                writeln!(f, "Best board: {}", board)?;
+            }
+        }
+        if let Some(n) = self.reachable {
+            writeln!(f, "Reachable states: {}.", n)?;
+        }
+        if self.pruned.iter().any(|&(_, n)| n > 0) {
+            let counts: Vec<String> = self
+                .pruned
+                .iter()
+                .map(|(d, n)| format!("{} {}", n, d.name()))
+                .collect();
+            writeln!(f, "Pruned lost states: {}.", counts.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl crate::Solution for Solution {
+    fn is_solved(&self) -> bool {
+        self.moves.is_some()
+    }
+
+    fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    fn step_count(&self) -> Option<usize> {
+        self.moves.as_ref().map(|m| m.len())
+    }
+
+    fn gave_up(&self) -> Option<GaveUp> {
+        self.gave_up
+    }
+
+    fn is_unsolvable(&self) -> bool {
+        self.unsolvable
+    }
+
+    fn next_move(&self) -> Option<String> {
+        let m = self.moves.as_ref()?.first()?;
+        Some(Notated(m, self.notation).to_string())
+    }
+
+    fn to_json(&self) -> Json {
+        let moves_json = |moves: &[Move]| Json::Array(moves.iter().map(Move::to_json).collect());
+        let mut v = match &self.moves {
+            Some(moves) => Json::object([
+                ("moves", moves_json(moves)),
+                ("proven_optimal", self.proven_optimal.into()),
+            ]),
+            None if self.gave_up.is_some() || self.unsolvable => {
+                Json::object([("partial_moves", moves_json(&self.partial_moves))])
+            }
+            None => return Json::Null,
+        };
+        if let Some(n) = self.shortened_from {
+            v.push("shortened_from", n.into());
+        }
+        if let Some(n) = self.reachable {
+            v.push("reachable", n.into());
+        }
+        if self.pruned.iter().any(|&(_, n)| n > 0) {
+            let counts = self.pruned.iter().map(|&(d, n)| (d.name(), n.into()));
+            v.push("pruned", Json::object(counts));
+        }
+        v
+    }
+}
+
+impl Move {
+    /// Columns are 1-based, matching the text output.
+    fn to_json(&self) -> Json {
+        let cards: Vec<&str> = self.cards.iter().map(|c| c.name(Notation::Latin)).collect();
+        Json::object([
+            ("from", Json::from(self.from + 1)),
+            ("to", (self.to + 1).into()),
+            ("cards", cards.into()),
+            ("to_slot", self.to_slot.into()),
+        ])
+    }
+}
+
+impl crate::Solver for State {
+    type Options = Options;
+    type Solution = Solution;
+
+    fn parse(input: &str) -> Result<Self, ParseError> {
+        State::parse(input)
+    }
+
+    fn solve_with_limits(&self, options: &Options, limits: &Limits) -> Solution {
+        let mut searcher = Searcher {
+            threads: options.threads,
+            deterministic: options.deterministic,
+            exhaustive: options.exhaustive,
+            rules: options.rules.clone(),
+            heuristic: options.heuristic.clone(),
+            tablebase: options.tablebase.clone().filter(|t| {
+                if t.rules != options.rules {
+                    log!(Level::Warn, "Ignoring a tablebase for rules {}", t.rules);
+                }
+                t.rules == options.rules && !options.exhaustive
+            }),
+            prune: !options.keep_deadlocks && !options.exhaustive,
+            ..Default::default()
+        };
+        if let Some(dir) = &options.spill_dir {
+            if let Err(e) = searcher.spill_to(dir) {
+                log!(Level::Warn, "Cannot spill to {}: {}", dir.display(), e);
+            }
+        }
+        let mut moves = if options.optimal {
+            searcher.search_optimal(*self, limits)
+        } else {
+            searcher.search(*self, limits)
+        };
+        // The heap only runs out once all reachable states are searched, or
+        // pruned as lost.
+        let searched_all = searcher.gave_up.is_none() && (moves.is_none() || options.exhaustive);
+        let pruned_any = searcher.pruned.iter().any(|&n| n > 0);
+        let unsolvable = searched_all && moves.is_none();
+        let partial_moves = if searcher.gave_up.is_some() || unsolvable {
+            searcher.explain_solution(*self, searcher.best_state_id)
+        } else {
+            Vec::new()
+        };
+        if searcher.states.spilled_bytes() > 0 {
+            info!(
+                "Spilled {:.1} MB of states",
+                searcher.states.spilled_bytes() as f64 / 1e6
+            );
+        }
+        let mut shortened_from = None;
+        if let (Some(time), Some(found), false) = (options.shorten, &moves, options.optimal) {
+            let mut path = vec![*self];
+            path.extend(found.iter().map(|m| m.board));
+            for state in &mut path {
+                state.normalize(&options.rules);
+            }
+            let deadline = Instant::now() + time;
+            let path = shorten_path(path, &options.rules, deadline, &limits.cancel);
+            info!("Shortened from {} to {} steps", found.len(), path.len() - 1);
+            shortened_from = Some(found.len());
+            moves = Some(searcher.explain_path(*self, &path));
+        }
+        let stats = Stats {
+            searched: searcher.states.len(),
+            cache_hits: searcher.cache_hit_count,
+            memory: searcher.memory_usage(0),
+        };
+        Solution {
+            proven_optimal: options.optimal && moves.is_some(),
+            moves,
+            gave_up: searcher.gave_up,
+            unsolvable,
+            reachable: (searched_all && !pruned_any).then_some(searcher.states.len()),
+            partial_moves,
+            shortened_from,
+            pruned: Deadlock::ALL.into_iter().zip(searcher.pruned).collect(),
+            stats,
+            notation: options.notation,
+        }
+    }
+
+    fn verify(&self, solution: &str) -> Result<String, String> {
+        self.verify_with_rules(solution, &Rules::default(), Notation::default())
+    }
+}
+
+impl State {
+    /// [`Solver::verify`](crate::Solver::verify) with `rules`, naming cards
+    /// in `notation` in the errors.
+    pub fn verify_with_rules(
+        &self,
+        solution: &str,
+        rules: &Rules,
+        notation: Notation,
+    ) -> Result<String, String> {
+        let mut state = *self;
+        let mut step_count = 0;
+        for line in solution.lines() {
+            let (cards, col1, col2) = or!(parse_move_line(line)?, continue);
+            step_count += 1;
+            let cards_str: Vec<&str> = cards.iter().map(|c| c.name(notation)).collect();
+            let cards_str = format!("[{}]", cards_str.join(" "));
+            let board = Notated(&state, notation);
+            let err = |msg: String| format!("Step {}: {}. Board: {}", step_count, msg, board);
+            if col1 >= state.0.len() || col2 >= state.0.len() {
+                return Err(err(format!("invalid column {} -> {}", col1 + 1, col2 + 1)));
+            }
+            let n = cards.len() as u8;
+            let column = &state.0[col1];
+            if n == 0 || column.card_count() < n || column.last_n_cards(n) != cards {
+                return Err(err(format!(
+                    "column {} {} does not end with {}",
+                    col1 + 1,
+                    Notated(column, notation),
+                    cards_str
+                )));
+            }
+            let next = state
+                .next_states_by_moving(col1, col2, rules)
+                .into_iter()
+                .find(|(_, step)| step.card_count == n);
+            state = match next {
+                Some((next_state, _)) => next_state,
+                None => {
+                    return Err(err(format!(
+                        "cannot move {} from {} -> {}",
+                        cards_str,
+                        col1 + 1,
+                        col2 + 1
+                    )))
+                }
+            };
+        }
+        if step_count == 0 {
+            return Err("no \"Move [..] from a -> b\" lines found".to_string());
+        }
+        if !state.is_success() {
+            return Err(format!(
+                "not solved after {} steps. Board: {}",
+                step_count,
+                Notated(&state, notation)
+            ));
+        }
+        Ok(format!("Solved in {} steps.", step_count))
+    }
+}
+
+/// A random deal, in the format [`State::parse_with_rules`] reads. The same
+/// seed and rules give the same deal.
+pub fn deal(seed: u64, rules: &Rules, notation: Notation) -> String {
+    let mut cards: Vec<Card> = (rules.lowest.0..=rules.highest.0)
+        .flat_map(|v| std::iter::repeat_n(Card(v), rules.copies as usize))
+        .collect();
+    Rng::new(seed).shuffle(&mut cards);
+    let mut out = String::new();
+    for row in cards.chunks(rules.columns as usize) {
+        let names: Vec<&str> = row.iter().map(|c| c.name(notation)).collect();
+        out += &names.join(" ");
+        out.push('\n');
+    }
+    out
+}
+
+/// States searched after each first move, to tell whether it loses.
+const FIRST_MOVE_STATES: usize = 200_000;
+
+/// How hard a deal is. See [`State::rate`].
+#[derive(Clone, Debug)]
+pub struct Rating {
+    /// States the default search needed to find a solution.
+    pub searched: usize,
+    /// Average distinct moves from the positions of the solution.
+    pub branching: f64,
+    /// Moves of the solution that put a card in a free slot.
+    pub slot_moves: usize,
+    /// Steps of the shortest solution found.
+    pub steps: Option<usize>,
+    /// Whether no solution has fewer steps.
+    pub proven_optimal: bool,
+    /// Distinct first moves, and how many of them lead to a position without
+    /// a solution, or could not be decided within the limits.
+    pub first_moves: usize,
+    pub dead_first_moves: usize,
+    pub unknown_first_moves: usize,
+    pub unsolvable: bool,
+    /// Set if the search gave up before finding a solution.
+    pub gave_up: Option<GaveUp>,
+}
+
+impl Rating {
+    /// Difficulty from 0 (trivial) to 100, if the deal was solved. A
+    /// weighted mean of the measures, each scaled to 0 to 1 over the range
+    /// seen on real deals.
+    pub fn difficulty(&self) -> Option<f64> {
+        let steps = self.steps? as f64;
+        let scale = |v: f64, easy: f64, hard: f64| ((v - easy) / (hard - easy)).clamp(0.0, 1.0);
+        let searched = scale((self.searched.max(1) as f64).log10(), 3.0, 7.0);
+        let length = scale(steps, 25.0, 60.0);
+        let dead = match self.first_moves {
+            0 => 0.0,
+            n => self.dead_first_moves as f64 / n as f64,
+        };
+        let slots = scale(self.slot_moves as f64 / steps.max(1.0), 0.0, 0.5);
+        // Fewer moves to choose from leave less room for mistakes to be fixed.
+        let branching = 1.0 - scale(self.branching, 15.0, 35.0);
+        let score = 0.35 * searched + 0.25 * length + 0.2 * dead + 0.1 * slots + 0.1 * branching;
+        Some(100.0 * score)
+    }
+}
+
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.difficulty(), self.gave_up) {
+            (Some(d), _) => writeln!(f, "Difficulty: {:.0}/100.", d)?,
+            (None, _) if self.unsolvable => writeln!(f, "Unsolvable.")?,
+            (None, Some(reason)) => writeln!(f, "Unknown: gave up ({}).", reason)?,
+            (None, None) => writeln!(f, "Unknown: no solution found.")?,
+        }
+        writeln!(f, "Searched states: {}.", self.searched)?;
+        if let Some(steps) = self.steps {
+            let proven = if self.proven_optimal {
+                ""
+            } else {
+                ", not proven"
+            };
+            writeln!(f, "Fewest steps: {}{}.", steps, proven)?;
+            writeln!(f, "Moves to a free slot: {}.", self.slot_moves)?;
+            writeln!(f, "Moves per position: {:.1}.", self.branching)?;
+        }
+        write!(
+            f,
+            "Losing first moves: {} of {}",
+            self.dead_first_moves, self.first_moves
+        )?;
+        if self.unknown_first_moves > 0 {
+            write!(f, " ({} undecided)", self.unknown_first_moves)?;
+        }
+        writeln!(f, ".")
+    }
+}
+
+impl State {
+    /// Measure how hard the deal is: run the default and the optimal search,
+    /// and a search after each first move. Each search has `limits`.
+    pub fn rate(&self, options: &Options, limits: &Limits) -> Rating {
+        use crate::Solver as _;
+        let rules = &options.rules;
+        let default_options = Options {
+            optimal: false,
+            exhaustive: false,
+            shorten: None,
+            ..options.clone()
+        };
+        let found = self.solve_with_limits(&default_options, limits);
+        let mut rating = Rating {
+            searched: found.stats.searched,
+            branching: 0.0,
+            slot_moves: 0,
+            steps: None,
+            proven_optimal: false,
+            first_moves: 0,
+            dead_first_moves: 0,
+            unknown_first_moves: 0,
+            unsolvable: found.unsolvable,
+            gave_up: found.gave_up,
+        };
+
+        let first_moves = self.distinct_next_states(rules);
+        rating.first_moves = first_moves.len();
+        let first_move_limits = Limits {
+            max_states: Some(
+                limits
+                    .max_states
+                    .unwrap_or(usize::MAX)
+                    .min(FIRST_MOVE_STATES),
+            ),
+            progress: None,
+            ..limits.clone()
+        };
+        for state in first_moves {
+            let solution = state.solve_with_limits(&default_options, &first_move_limits);
+            if solution.unsolvable {
+                rating.dead_first_moves += 1;
+            } else if solution.moves.is_none() {
+                rating.unknown_first_moves += 1;
+            }
+        }
+
+        let mut moves = match found.moves {
+            Some(moves) => moves,
+            None => return rating,
+        };
+        let optimal = self.solve_with_limits(
+            &Options {
+                optimal: true,
+                ..default_options
+            },
+            limits,
+        );
+        if let Some(shortest) = optimal.moves {
+            moves = shortest;
+            rating.proven_optimal = true;
+        }
+        rating.steps = Some(moves.len());
+        rating.slot_moves = moves.iter().filter(|m| m.to_slot).count();
+        let positions = std::iter::once(*self).chain(moves.iter().map(|m| m.board));
+        let branches: usize = positions
+            .take(moves.len())
+            .map(|state| state.distinct_next_states(rules).len())
+            .sum();
+        rating.branching = branches as f64 / moves.len().max(1) as f64;
+        rating
+    }
+}
+
+/// What happens after a move. See [`State::rank_moves`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outlook {
+    /// Can still be won, in this many more moves.
+    Wins { moves_left: usize, proven: bool },
+    /// Cannot be won any more.
+    Loses,
+    /// The search gave up.
+    Unknown(GaveUp),
+}
+
+/// A move from a position, and its outlook.
+#[derive(Clone, Debug)]
+pub struct RankedMove {
+    pub m: Move,
+    pub outlook: Outlook,
+    /// Whether the board is the same as before, up to the order of columns.
+    pub no_change: bool,
+    /// Rank of an earlier move that leads to the same board, up to the
+    /// order of columns. 1-based.
+    pub same_as: Option<usize>,
+}
+
+/// All moves from a position, best first, and the notation to show them in.
+#[derive(Clone, Debug)]
+pub struct MoveRanking(pub Vec<RankedMove>, pub Notation);
+
+impl fmt::Display for MoveRanking {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return writeln!(f, "No move is possible.");
+        }
+        writeln!(f, "Rank  Outlook  Left  Move")?;
+        for (i, r) in self.0.iter().enumerate() {
+            let (outlook, left) = match &r.outlook {
+                Outlook::Wins { moves_left, proven } => {
+                    let bound = if *proven { "" } else { "<=" };
+                    ("wins", format!("{}{}", bound, moves_left))
+                }
+                Outlook::Loses => ("loses", "-".to_string()),
+                Outlook::Unknown(_) => ("unknown", "-".to_string()),
+            };
+            let m = Notated(&r.m, self.1);
+            write!(f, "{:>4}  {:<7}  {:>4}  {}", i + 1, outlook, left, m)?;
+            if r.no_change {
+                write!(f, " (no change)")?;
+            } else if let Some(rank) = r.same_as {
+                write!(f, " (same as {})", rank)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl State {
+    /// Search after every move [`State::next_states`] generates, and rank
+    /// them: winning moves by the moves left, then moves that could not be
+    /// decided, then losing moves. Each distinct board is searched once,
+    /// with `limits`: for the fewest moves, then without `optimal` if that
+    /// gives up.
+    pub fn rank_moves(&self, options: &Options, limits: &Limits) -> MoveRanking {
+        use crate::Solver as _;
+        let rules = &options.rules;
+        let mut current = *self;
+        current.normalize(rules);
+        let default_options = Options {
+            optimal: false,
+            exhaustive: false,
+            shorten: None,
+            ..options.clone()
+        };
+        let optimal_options = Options {
+            optimal: true,
+            ..default_options.clone()
+        };
+        let mut outlooks: HashMap<State, Outlook> = HashMap::new();
+        let mut ranked: Vec<(State, RankedMove)> = Vec::new();
+        let n = self.0.len();
+        let moves = (0..n).flat_map(|col1| {
+            (0..n).flat_map(move |col2| {
+                let next = self.next_states_by_moving(col1, col2, rules);
+                next.into_iter().map(move |(s, step)| (s, step, col1, col2))
+            })
+        });
+        for (next_state, step, col1, col2) in moves {
+            let mut m = step.explain(self);
+            // Identical columns, like empty ones, are told apart here.
+            (m.from, m.to) = (col1, col2);
+            m.board = next_state;
+            m.board_score = next_state.score(rules, &options.heuristic);
+            let mut normalized = next_state;
+            normalized.normalize(rules);
+            let outlook = outlooks.entry(normalized).or_insert_with(|| {
+                let optimal = next_state.solve_with_limits(&optimal_options, limits);
+                let solution = match optimal.gave_up {
+                    Some(_) => next_state.solve_with_limits(&default_options, limits),
+                    None => optimal,
+                };
+                match (&solution.moves, solution.gave_up) {
+                    (Some(moves), _) => Outlook::Wins {
+                        moves_left: moves.len(),
+                        proven: solution.proven_optimal,
+                    },
+                    (None, Some(reason)) => Outlook::Unknown(reason),
+                    (None, None) => Outlook::Loses,
+                }
+            });
+            ranked.push((
+                normalized,
+                RankedMove {
+                    m,
+                    outlook: outlook.clone(),
+                    no_change: normalized == current,
+                    same_as: None,
+                },
+            ));
+        }
+        let order = |o: &Outlook| match o {
+            Outlook::Wins { moves_left, .. } => (0, *moves_left),
+            Outlook::Unknown(_) => (1, 0),
+            Outlook::Loses => (2, 0),
+        };
+        // Stable, so moves to the same board stay in generation order.
+        ranked.sort_by_key(|(_, r)| order(&r.outlook));
+        let mut first_rank: HashMap<State, usize> = HashMap::new();
+        for (i, (state, r)) in ranked.iter_mut().enumerate() {
+            match first_rank.get(state) {
+                Some(&rank) => r.same_as = Some(rank),
+                None => {
+                    first_rank.insert(*state, i + 1);
+                }
+            }
+        }
+        let moves = ranked.into_iter().map(|(_, r)| r).collect();
+        MoveRanking(moves, options.notation)
+    }
+}
+
+/// Parse "Move [cards] from a -> b". Columns are converted to 0-based.
+/// Return `None` for other lines.
+fn parse_move_line(line: &str) -> Result<Option<(Vec<Card>, u8, u8)>, String> {
+    let rest = or!(line.split_once("Move ["), return Ok(None)).1;
+    let bad_line = || format!("cannot parse move: {}", line.trim());
+    let (cards, rest) = rest.split_once(']').ok_or_else(bad_line)?;
+    let cards: Vec<Card> = cards
+        .split_whitespace()
+        .map(Card::from_str)
+        .collect::<Option<_>>()
+        .ok_or_else(bad_line)?;
+    let rest = rest
+        .trim_start()
+        .strip_prefix("from")
+        .ok_or_else(bad_line)?;
+    let (col1, rest) = rest.split_once("->").ok_or_else(bad_line)?;
+    let col2: String = rest
+        .trim_start()
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    let col1: u8 = col1.trim().parse().map_err(|_| bad_line())?;
+    let col2: u8 = col2.parse().map_err(|_| bad_line())?;
+    if col1 == 0 || col2 == 0 {
+        return Err(bad_line());
+    }
+    Ok(Some((cards, col1 - 1, col2 - 1)))
+}
+
+pub const INFO: GameInfo = GameInfo {
+    name: "cjul",
+    aliases: &["cluj"],
+    description: "Cluj Solitaire from the Zachtronics Solitaire Collection",
+    format: "\
+6 lines of 6 cards. Each line is a row, from top to bottom.
+Cards are 6, 7, 8, 9, 10, V, D, K, T (case insensitive). The face cards
+can also be written В, Д, К, Т as in the game, or J, Q, K, A.
+Spaces and '1' are optional.
+
+A mid-game position has a line per column instead, like \"3: T 9 8 | 7\":
+the column number, its cards from the bottom to the top, and the card in
+its free slot after '|'. Columns that are not listed are empty.
+
+Other rules (--rules) change the number of rows and columns, and the ranks
+in the deck, which go from 2 to T.",
+    example: include_str!("../data/cjul/7.in"),
+};
+
+const CARD_STRS: [&str; 13] = [
+    "2", "3", "4", "5", "6", "7", "8", "9", "10", "V", "D", "K", "T",
+];
+
+// Face cards of the other notations. Jack, queen, king and ace.
+const CYRILLIC_FACES: [&str; 4] = ["В", "Д", "К", "Т"];
+const ENGLISH_FACES: [&str; 4] = ["J", "Q", "K", "A"];
+
+/// How cards are named in the output. All of them are accepted as input.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Notation {
+    /// Transliterated from the Russian names in the game: V, D, K, T.
+    #[default]
+    Latin,
+    /// As shown in the game: В, Д, К, Т.
+    Cyrillic,
+    /// J, Q, K, A.
+    English,
+}
+
+impl FromStr for Notation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "latin" => Ok(Self::Latin),
+            "cyrillic" => Ok(Self::Cyrillic),
+            "english" => Ok(Self::English),
+            _ => Err(format!("unknown notation: {:?}", s)),
+        }
+    }
+}
+
+/// Shows a [`Column`], [`State`] or [`Move`] with card names in a
+/// [`Notation`]. Their `Display` uses the default notation.
+pub struct Notated<'a, T>(pub &'a T, pub Notation);
 
 impl fmt::Debug for Card {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let s = CARD_STRS[self.0 as usize];
-        f.write_str(s)
+        f.write_str(self.name(Notation::Latin))
     }
 }
 
@@ -103,38 +959,51 @@ impl fmt::Debug for Column {
     }
 }
 
-impl fmt::Display for Column {
+impl fmt::Display for Notated<'_, Column> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.is_dead() {
+        let Notated(column, notation) = *self;
+        if column.is_dead() {
             return f.write_str("[..]");
         }
         let mut cards = Vec::new();
-        for span in self.0.as_ref() {
+        for span in column.0.as_ref() {
             for i in (span.bottom().0..=span.top().0).rev() {
-                cards.push(format!("{:?}", Card(i)));
+                cards.push(Card(i).name(notation));
             }
         }
-        if let Some(c) = self.1 {
-            cards.push(format!("{:?}", c));
+        if let Some(c) = column.1 {
+            cards.push(c.name(notation));
         }
         let card_str = cards.join(" ");
         write!(f, "[{}]", card_str)
     }
 }
 
-impl fmt::Display for State {
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Notated(self, Notation::default()).fmt(f)
+    }
+}
+
+impl fmt::Display for Notated<'_, State> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_char('[')?;
-        for (i, column) in self.0.iter().enumerate() {
+        for (i, column) in self.0 .0.as_ref().iter().enumerate() {
             if i != 0 {
                 f.write_char(' ')?;
             }
-            column.fmt(f)?;
+            Notated(column, self.1).fmt(f)?;
         }
         f.write_char(']')
     }
 }
 
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Notated(self, Notation::default()).fmt(f)
+    }
+}
+
 impl fmt::Debug for State {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.0.fmt(f)
@@ -142,76 +1011,295 @@ impl fmt::Debug for State {
 }
 
 impl Card {
-    fn from_str(s: &str) -> Card {
-        let s = s.to_ascii_uppercase();
+    /// Name of the card in `notation`.
+    pub fn name(self, notation: Notation) -> &'static str {
+        let faces = match notation {
+            Notation::Latin => return CARD_STRS[self.0 as usize],
+            Notation::Cyrillic => CYRILLIC_FACES,
+            Notation::English => ENGLISH_FACES,
+        };
+        match self.0.checked_sub(9) {
+            Some(i) => faces[i as usize],
+            None => CARD_STRS[self.0 as usize],
+        }
+    }
+
+    /// Parse a card name in any notation, case insensitive.
+    fn from_str(s: &str) -> Option<Card> {
+        let s = s.to_uppercase();
+        let face = |faces: [&str; 4]| faces.iter().position(|&c| c == s).map(|i| i + 9);
         let v = match s.as_str() {
-            "6" => 0,
-            "7" => 1,
-            "8" => 2,
-            "9" => 3,
-            "10" | "0" | "1" => 4,
-            "V" => 5,
-            "D" => 6,
-            "K" => 7,
-            "T" => 8,
-            _ => panic!("unknwon card str: {}", s),
+            "10" | "0" | "1" => 8,
+            _ => CARD_STRS
+                .iter()
+                .position(|&c| c == s)
+                .or_else(|| face(CYRILLIC_FACES))
+                .or_else(|| face(ENGLISH_FACES))?,
         };
-        Self(v)
+        Some(Self(v as u8))
     }
 }
 
-impl Sub<u8> for Card {
-    type Output = Self;
-
-    fn sub(self, rhs: u8) -> Self::Output {
-        Self(self.0 - rhs)
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            columns: 6,
+            lowest: Card(4),
+            highest: Card(12),
+            copies: 4,
+            slots: true,
+            run_len: 9,
+        }
     }
 }
 
-impl Add<u8> for Card {
-    type Output = Self;
+impl FromStr for Rules {
+    type Err = String;
 
-    fn add(self, rhs: u8) -> Self::Output {
-        Self(self.0 + rhs)
+    /// Parse a comma separated list of a preset (`standard`, or `hard` without
+    /// free slots) and `key=value` changes to it, like
+    /// `hard,columns=5,ranks=6-K,copies=3,run=8`.
+    fn from_str(spec: &str) -> Result<Self, String> {
+        let mut rules = Rules::default();
+        // The number of ranks unless given, whichever key comes first.
+        let mut run_len = None;
+        for (i, item) in spec.split(',').map(str::trim).enumerate() {
+            let bad_value = || format!("invalid rule: {:?}", item);
+            let (key, value) = match item.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None if i == 0 && item == "standard" => continue,
+                None if i == 0 && item == "hard" => {
+                    rules.slots = false;
+                    continue;
+                }
+                None => return Err(format!("unknown rules: {:?}", item)),
+            };
+            match key {
+                "columns" => rules.columns = value.parse().map_err(|_| bad_value())?,
+                "ranks" => {
+                    let (lowest, highest) = value.split_once('-').ok_or_else(bad_value)?;
+                    rules.lowest = Card::from_str(lowest.trim()).ok_or_else(bad_value)?;
+                    rules.highest = Card::from_str(highest.trim()).ok_or_else(bad_value)?;
+                }
+                "copies" => rules.copies = value.parse().map_err(|_| bad_value())?,
+                "slots" => {
+                    rules.slots = match value {
+                        "yes" | "on" => true,
+                        "no" | "off" => false,
+                        _ => return Err(bad_value()),
+                    }
+                }
+                "run" => run_len = Some(value.parse().map_err(|_| bad_value())?),
+                _ => return Err(format!("unknown rule: {:?}", key)),
+            }
+        }
+        if rules.lowest <= rules.highest {
+            rules.run_len = run_len.unwrap_or(rules.rank_count());
+        }
+        rules.validate()?;
+        Ok(rules)
     }
 }
 
-impl Span {
-    fn top(self) -> Card {
-        self.0
+impl fmt::Display for Rules {
+    /// The spec that parses back to these rules.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "columns={},ranks={}-{},copies={},slots={},run={}",
+            self.columns,
+            self.lowest.name(Notation::Latin),
+            self.highest.name(Notation::Latin),
+            self.copies,
+            if self.slots { "yes" } else { "no" },
+            self.run_len
+        )
     }
-    fn len(self) -> u8 {
-        self.1
+}
+
+impl Rules {
+    /// Check that a deal fits the board.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_COLUMNS as u8).contains(&self.columns) {
+            return Err(format!("columns must be 1 to {}", MAX_COLUMNS));
+        }
+        if self.lowest > self.highest || self.copies == 0 {
+            return Err("the deck is empty".to_string());
+        }
+        let cards = self.rank_count() as usize * self.copies as usize;
+        let rows = cards / self.columns as usize;
+        if !cards.is_multiple_of(self.columns as usize) || rows > 6 {
+            return Err(format!(
+                "{} cards do not fit in {} columns of up to 6 rows",
+                cards, self.columns
+            ));
+        }
+        if !(1..=self.rank_count()).contains(&self.run_len) {
+            return Err(format!("run must be 1 to {}", self.rank_count()));
+        }
+        Ok(())
     }
-    fn bottom(self) -> Card {
-        Card(self.0 .0 + 1 - self.1)
+
+    fn rank_count(&self) -> u8 {
+        self.highest.0 + 1 - self.lowest.0
     }
-    fn can_accept_card(self, card: Card) -> bool {
-        card + 1 == self.bottom()
+
+    /// Rows of a deal.
+    fn rows(&self) -> usize {
+        self.rank_count() as usize * self.copies as usize / self.columns as usize
     }
-    fn accept_span_size(self, span: Span) -> u8 {
-        let b = self.bottom();
-        let c = if b > span.bottom() && span.top() + 1 >= b {
-            b.0 - span.bottom().0
-        } else {
-            0
-        };
-        c
+
+    fn parse_card(&self, c: char) -> Result<Card, String> {
+        match Card::from_str(&c.to_string()) {
+            Some(card) if self.lowest <= card && card <= self.highest => Ok(card),
+            Some(card) => Err(format!("card {:?} is not in the deck", card)),
+            None => Err(format!("unknown card {:?}", c)),
+        }
     }
-    fn extend_size(&mut self, len: u8) {
-        self.1 += len;
+
+    fn is_complete(&self, column: &Column) -> bool {
+        column.1.is_none() && column.0.len() == 1 && column.0[0].len() == self.run_len
     }
-    fn shrink_size(&mut self, len: u8) {
-        self.1 -= len;
+}
+
+impl Default for Heuristic {
+    fn default() -> Self {
+        Self {
+            tidy: 1,
+            free_slot: 1,
+            empty_column: 0,
+            complete: 0,
+            stuck: 10,
+        }
     }
-    fn from_card(card: Card) -> Self {
-        Span(card, 1)
+}
+
+impl FromStr for Heuristic {
+    type Err = String;
+
+    /// Parse a profile of `key = value` lines (or comma separated items),
+    /// like the `Display` output. Missing keys keep their default. `#`
+    /// starts a comment.
+    fn from_str(profile: &str) -> Result<Self, String> {
+        let mut h = Heuristic::default();
+        let items = profile
+            .lines()
+            .map(|l| l.split('#').next().unwrap_or_default())
+            .flat_map(|l| l.split(','))
+            .map(str::trim)
+            .filter(|item| !item.is_empty());
+        for item in items {
+            let (key, value) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expect key = value: {:?}", item))?;
+            let value = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid weight: {:?}", item))?;
+            *h.weight_mut(key.trim())
+                .ok_or_else(|| format!("unknown weight: {:?}", key.trim()))? = value;
+        }
+        h.validate()?;
+        Ok(h)
+    }
+}
+
+impl fmt::Display for Heuristic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in self.weights() {
+            writeln!(f, "{} = {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Heuristic {
+    /// Check that scores fit in a byte.
+    pub fn validate(&self) -> Result<(), String> {
+        let per_column = 6 * self.tidy as usize
+            + self.free_slot as usize
+            + self.empty_column as usize
+            + self.complete as usize;
+        if per_column * MAX_COLUMNS > u8::MAX as usize {
+            return Err("weights are too large".to_string());
+        }
+        Ok(())
+    }
+
+    /// Weights by name.
+    pub fn weights(&self) -> [(&'static str, u8); 5] {
+        [
+            ("tidy", self.tidy),
+            ("free_slot", self.free_slot),
+            ("empty_column", self.empty_column),
+            ("complete", self.complete),
+            ("stuck", self.stuck),
+        ]
+    }
+
+    pub fn weight_mut(&mut self, name: &str) -> Option<&mut u8> {
+        Some(match name {
+            "tidy" => &mut self.tidy,
+            "free_slot" => &mut self.free_slot,
+            "empty_column" => &mut self.empty_column,
+            "complete" => &mut self.complete,
+            "stuck" => &mut self.stuck,
+            _ => return None,
+        })
+    }
+}
+
+impl Sub<u8> for Card {
+    type Output = Self;
+
+    fn sub(self, rhs: u8) -> Self::Output {
+        Self(self.0 - rhs)
+    }
+}
+
+impl Add<u8> for Card {
+    type Output = Self;
+
+    fn add(self, rhs: u8) -> Self::Output {
+        Self(self.0 + rhs)
+    }
+}
+
+impl Span {
+    fn top(self) -> Card {
+        self.0
+    }
+    fn len(self) -> u8 {
+        self.1
+    }
+    fn bottom(self) -> Card {
+        Card(self.0 .0 + 1 - self.1)
+    }
+    fn can_accept_card(self, card: Card) -> bool {
+        card + 1 == self.bottom()
+    }
+    fn accept_span_size(self, span: Span) -> u8 {
+        let b = self.bottom();
+        if b > span.bottom() && span.top() + 1 >= b {
+            b.0 - span.bottom().0
+        } else {
+            0
+        }
+    }
+    fn extend_size(&mut self, len: u8) {
+        self.1 += len;
+    }
+    fn shrink_size(&mut self, len: u8) {
+        self.1 -= len;
+    }
+    fn from_card(card: Card) -> Self {
+        Span(card, 1)
     }
 }
 
 impl Column {
     fn is_dead(&self) -> bool {
-        self.0.len() == 1 && self.0[0].len() == 9
+        self.2
     }
     fn is_empty(&self) -> bool {
         self.0.len() == 0 && self.card_slot().is_none()
@@ -219,6 +1307,10 @@ impl Column {
     fn card_slot(&self) -> Option<Card> {
         self.1
     }
+    fn card_count(&self) -> u8 {
+        let n: u8 = self.0.as_ref().iter().map(|s| s.len()).sum();
+        n + self.1.is_some() as u8
+    }
     fn movable_span(&self) -> Option<Span> {
         if self.is_dead() {
             return None;
@@ -228,125 +1320,270 @@ impl Column {
             None => self.0.last().cloned(),
         }
     }
+    /// Column of `cards` (bottom to top), and a card in the free slot.
+    fn from_cards(cards: &[Card], slot: Option<Card>, rules: &Rules) -> Self {
+        let mut column = Column(NVec::default(), slot, false);
+        for &card in cards {
+            match column.0.pop() {
+                Some(mut span) if span.can_accept_card(card) => {
+                    span.extend_size(1);
+                    column.0.push(span);
+                }
+                Some(span) => {
+                    column.0.push(span);
+                    column.0.push(Span::from_card(card));
+                }
+                None => column.0.push(Span::from_card(card)),
+            }
+        }
+        column.2 = rules.is_complete(&column);
+        column
+    }
+
     /// For "explain" use-case.
-    fn explain_last_n_cards(&self, mut n: u8) -> String {
+    fn last_n_cards(&self, mut n: u8) -> Vec<Card> {
         let mut cards = Vec::new();
         if let Some(c) = self.1 {
             if n > 0 {
                 n -= 1;
-                cards.push(format!("{:?}", c));
+                cards.push(c);
             }
         }
-        let span = self.0.last().unwrap();
-        for i in (span.bottom().0..=span.top().0).take(n as usize) {
-            cards.push(format!("{:?}", Card(i)));
+        if n > 0 {
+            let span = self.0.last().unwrap();
+            for i in (span.bottom().0..=span.top().0).take(n as usize) {
+                cards.push(Card(i));
+            }
         }
         cards.reverse();
-        format!("[{}]", cards.join(" "))
+        cards
     }
 }
 
 impl State {
-    fn normalize(&mut self) {
-        self.0.sort_unstable();
-        self.validate();
-    }
-
-    fn parse(s: &str) -> Self {
-        let mut m: [[Card; 6]; 6] = Default::default();
-        let mut card_counts: HashMap<Card, usize> = HashMap::new();
-        let mut card_total = 0;
-        for (i, line) in s.lines().enumerate().take(6) {
-            for (j, c) in line
-                .chars()
-                .filter(|c| !c.is_whitespace() && *c != '1')
-                .enumerate()
-                .take(6)
-            {
-                let c = Card::from_str(&c.to_string());
-                *card_counts.entry(c).or_default() += 1;
-                if card_counts[&c] > 4 {
-                    panic!("too many {:?} cards", c);
+    fn normalize(&mut self, rules: &Rules) {
+        self.0.as_mut().sort_unstable();
+        self.validate(rules);
+    }
+
+    fn parse(s: &str) -> Result<Self, ParseError> {
+        Self::parse_with_rules(s, &Rules::default())
+    }
+
+    /// Parse a deal or a mid-game position of a game with `rules`.
+    pub fn parse_with_rules(s: &str, rules: &Rules) -> Result<Self, ParseError> {
+        rules.validate().map_err(ParseError::new)?;
+        if s.lines().any(|l| l.contains(':')) {
+            return Self::parse_position(s, rules);
+        }
+        let (row_count, column_count) = (rules.rows(), rules.columns as usize);
+        let mut m: Vec<Vec<Card>> = Vec::new();
+        // Rows that each card appears in.
+        let mut card_rows: [Vec<usize>; 13] = Default::default();
+        for (i, line) in s.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let row = m.len();
+            if row >= row_count {
+                return Err(ParseError::at_line(
+                    i,
+                    format!("expected {} rows", row_count),
+                ));
+            }
+            let mut cards = Vec::new();
+            for (j, c) in line.chars().enumerate() {
+                if c.is_whitespace() || c == '1' {
+                    continue;
+                }
+                let card = rules.parse_card(c).map_err(|e| ParseError::at(i, j, e))?;
+                if cards.len() >= column_count {
+                    return Err(ParseError::at(
+                        i,
+                        j,
+                        format!("row {} has more than {} cards", row + 1, column_count),
+                    ));
                 }
-                card_total += 1;
-                m[i][j] = c;
+                card_rows[card.0 as usize].push(row);
+                cards.push(card);
             }
+            if cards.len() < column_count {
+                return Err(ParseError::at_line(
+                    i,
+                    format!("row {} has only {} cards", row + 1, cards.len()),
+                ));
+            }
+            m.push(cards);
+        }
+        if m.len() < row_count {
+            return Err(ParseError::new(format!(
+                "expected {} rows, found {}",
+                row_count,
+                m.len()
+            )));
         }
-        if card_total != 36 {
-            panic!("wrong number of cards {} (expect 36)", card_total);
+        for (i, rows) in card_rows.iter().enumerate() {
+            if rows.len() > rules.copies as usize {
+                return Err(ParseError::too_many(
+                    Card(i as u8).name(Notation::Latin),
+                    rows,
+                ));
+            }
         }
-        Self::from_grid(m)
+        Ok(Self::from_grid(&m, rules))
     }
 
-    fn from_grid(m: [[Card; 6]; 6]) -> State {
-        let mut s = State::default();
-        for col in 0..6 {
-            for row in 0..6 {
-                let card = m[row][col];
-                let cards = &mut s.0[col].0;
-                match cards.pop() {
-                    Some(mut span) => {
-                        if span.can_accept_card(card) {
-                            span.extend_size(1);
-                            cards.push(span);
-                        } else {
-                            cards.push(span);
-                            cards.push(Span::from_card(card));
-                        }
-                    }
-                    None => {
-                        cards.push(Span::from_card(card));
-                    }
+    /// Parse a mid-game position: lines of "column: cards | slot card".
+    fn parse_position(s: &str, rules: &Rules) -> Result<Self, ParseError> {
+        let mut state = State::default();
+        for _ in 0..rules.columns {
+            state.0.push(Column::default());
+        }
+        let mut listed = [false; MAX_COLUMNS];
+        // Columns that each card appears in.
+        let mut card_columns: [Vec<usize>; 13] = Default::default();
+        for (i, line) in s.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (col, cards) = line
+                .split_once(':')
+                .ok_or_else(|| ParseError::at_line(i, "expected \"column: cards\""))?;
+            let col = match col.trim().parse::<u8>() {
+                Ok(n) if (1..=rules.columns).contains(&n) => n - 1,
+                _ => return Err(ParseError::at_line(i, format!("invalid column {:?}", col))),
+            };
+            if listed[col as usize] {
+                return Err(ParseError::at_line(
+                    i,
+                    format!("column {} is listed twice", col + 1),
+                ));
+            }
+            listed[col as usize] = true;
+            let offset = line[..line.len() - cards.len()].chars().count();
+            let mut pile = Vec::new();
+            let mut slot = None;
+            let mut in_slot = false;
+            for (j, c) in cards.chars().enumerate() {
+                let j = j + offset;
+                if c.is_whitespace() || c == '1' {
+                    continue;
                 }
+                if c == '|' && !in_slot {
+                    in_slot = true;
+                    continue;
+                }
+                let card = rules.parse_card(c).map_err(|e| ParseError::at(i, j, e))?;
+                if !in_slot {
+                    pile.push(card);
+                } else if slot.is_none() {
+                    slot = Some(card);
+                } else {
+                    return Err(ParseError::at(i, j, "the slot holds only 1 card"));
+                }
+                card_columns[card.0 as usize].push(col as usize);
+            }
+            let runs = pile.windows(2).filter(|w| w[1] + 1 != w[0]).count() + 1;
+            if runs > 6 {
+                return Err(ParseError::at_line(
+                    i,
+                    format!("column {} has more than 6 runs of cards", col + 1),
+                ));
+            }
+            state.0[col] = Column::from_cards(&pile, slot, rules);
+        }
+        for card in rules.lowest.0..=rules.highest.0 {
+            let columns = &card_columns[card as usize];
+            if columns.len() != rules.copies as usize {
+                let columns: Vec<String> = columns.iter().map(|c| (c + 1).to_string()).collect();
+                return Err(ParseError::new(format!(
+                    "rank {} appears {} times, expected {} (columns {})",
+                    Card(card).name(Notation::Latin),
+                    columns.len(),
+                    rules.copies,
+                    columns.join(", ")
+                )));
             }
         }
-        dprintln!("Parsed State: {:?}", &s);
+        debug!("Parsed State: {:?}", &state);
+        Ok(state)
+    }
+
+    fn from_grid(m: &[Vec<Card>], rules: &Rules) -> State {
+        let mut s = State::default();
+        for col in 0..rules.columns as usize {
+            let cards: Vec<Card> = m.iter().map(|row| row[col]).collect();
+            s.0.push(Column::from_cards(&cards, None, rules));
+        }
+        debug!("Parsed State: {:?}", &s);
         s
     }
 
     /// Transfer to other states.
-    fn next_states<'a>(&'a self) -> impl Iterator<Item = (State, TransferStep)> + 'a {
-        (0..6).flat_map(move |col1| {
-            (0..6).flat_map(move |col2| self.next_states_by_moving(col1, col2).into_iter())
+    fn next_states<'a>(
+        &'a self,
+        rules: &'a Rules,
+    ) -> impl Iterator<Item = (State, TransferStep)> + 'a {
+        let n = self.0.len();
+        (0..n).flat_map(move |col1| {
+            (0..n).flat_map(move |col2| self.next_states_by_moving(col1, col2, rules).into_iter())
         })
     }
 
+    /// Normalized states after each move, without duplicates.
+    fn distinct_next_states(&self, rules: &Rules) -> Vec<State> {
+        let mut states: Vec<State> = self
+            .next_states(rules)
+            .map(|(mut s, _)| {
+                s.normalize(rules);
+                s
+            })
+            .collect();
+        states.sort();
+        states.dedup();
+        states
+    }
+
     // Generate state moving to another column.
-    fn next_states_by_moving(&self, col1: u8, col2: u8) -> Vec<(State, TransferStep)> {
-        let c1 = &self.0[col1 as usize];
-        let c2 = &self.0[col2 as usize];
+    fn next_states_by_moving(
+        &self,
+        col1: u8,
+        col2: u8,
+        rules: &Rules,
+    ) -> Vec<(State, TransferStep)> {
+        let c1 = &self.0[col1];
+        let c2 = &self.0[col2];
         let mut result = Vec::new();
         if col1 == col2 || c1.is_dead() || c2.is_dead() || c2.card_slot().is_some() {
             return result;
         }
         let span1 = or!(c1.movable_span(), return result);
+        let mut push = |n, slot2, step_score| {
+            let mut new_state = *self;
+            let step = new_state.unchecked_apply_moving_to(col1, col2, n, slot2, step_score);
+            new_state.mark_complete(rules);
+            result.push((new_state, step));
+        };
         match c2.movable_span() {
             Some(span2) => {
                 let n = span2.accept_span_size(span1);
                 if n > 0 {
                     // Do not use free slot.
-                    dprintln!(" Move {} cards from {} to {}", n, col1, col2);
-                    let mut new_state = *self;
-                    let step = new_state.unchecked_apply_moving_to(col1, col2, n, false, 1);
-                    result.push((new_state, step));
+                    trace!(" Move {} cards from {} to {}", n, col1, col2);
+                    push(n, false, 1);
                 }
-                if n != 1 && c1.card_slot().is_none() {
+                if rules.slots && n != 1 && c1.card_slot().is_none() {
                     // Use free slot.
-                    dprintln!(" Move 1 card from {} to {} (slot)", col1, col2);
-                    let mut new_state = *self;
-                    let step = new_state.unchecked_apply_moving_to(col1, col2, 1, true, 2);
-                    // dprintln!("  {:?}\n  => {:?}", self, &new_state);
-                    result.push((new_state, step));
+                    trace!(" Move 1 card from {} to {} (slot)", col1, col2);
+                    push(1, true, 2);
                 }
             }
             None => {
                 // Use free stack.
                 assert!(c2.is_empty());
                 for n in 1..=span1.len() {
-                    dprintln!(" Move {} cards from {} to {}", n, col1, col2);
-                    let mut new_state = *self;
-                    let step = new_state.unchecked_apply_moving_to(col1, col2, n, false, 3);
-                    result.push((new_state, step));
+                    trace!(" Move {} cards from {} to {}", n, col1, col2);
+                    push(n, false, 3);
                 }
             }
         }
@@ -363,67 +1600,92 @@ impl State {
         step_score: u8,
     ) -> TransferStep {
         let step = TransferStep {
-            from_column: self.0[col1 as usize],
-            to_column: self.0[col2 as usize],
+            from_column: self.0[col1],
+            to_column: self.0[col2],
             card_count: n,
             to_slot: slot2,
             step_score,
         };
-        let mut span = match self.0[col1 as usize].1.take() {
+        let mut span = match self.0[col1].1.take() {
             Some(card) => {
                 assert_eq!(n, 1);
                 Span::from_card(card)
             }
-            None => self.0[col1 as usize].0.pop().unwrap(),
+            None => self.0[col1].0.pop().unwrap(),
         };
         let span_bottom = span.bottom();
         span.shrink_size(n);
         if span.len() > 0 {
-            self.0[col1 as usize].0.push(span);
+            self.0[col1].0.push(span);
         }
         if slot2 {
             assert_eq!(n, 1);
-            self.0[col2 as usize].1 = Some(span_bottom);
+            self.0[col2].1 = Some(span_bottom);
         } else {
-            let span2 = match self.0[col2 as usize].0.pop() {
+            let span2 = match self.0[col2].0.pop() {
                 None => Span(span.bottom() - 1, n),
                 Some(mut span2) => {
                     span2.extend_size(n);
                     span2
                 }
             };
-            self.0[col2 as usize].0.push(span2);
+            self.0[col2].0.push(span2);
         }
         step
     }
 
+    /// Lock columns that became complete.
+    fn mark_complete(&mut self, rules: &Rules) {
+        for c in self.0.as_mut() {
+            c.2 = c.2 || rules.is_complete(c);
+        }
+    }
+
     fn is_success(&self) -> bool {
-        self.0.iter().all(|c| c.is_dead() || c.is_empty())
+        self.0.as_ref().iter().all(|c| c.is_dead() || c.is_empty())
     }
 
-    fn validate(&self) {
-        let mut cards = [0u8; 9];
-        for col in self.0.iter() {
+    fn validate(&self, rules: &Rules) {
+        assert!(self.is_valid(rules), "{:?} does not pass validation", self);
+    }
+
+    /// Whether the cards are the deck of `rules`.
+    fn is_valid(&self, rules: &Rules) -> bool {
+        let ranks = rules.lowest.0..=rules.highest.0;
+        let mut cards = [0u8; 13];
+        for col in self.0.as_ref() {
             for span in col.0.as_ref() {
-                assert!(span.len() > 0);
+                if span.len() == 0 || span.len() > span.top().0 + 1 {
+                    return false;
+                }
                 for v in span.bottom().0..=span.top().0 {
-                    assert!(v < 9);
+                    if !ranks.contains(&v) {
+                        return false;
+                    }
                     cards[v as usize] += 1;
                 }
             }
             if let Some(Card(v)) = col.1 {
+                if !ranks.contains(&v) {
+                    return false;
+                }
                 cards[v as usize] += 1;
             }
         }
-        assert_eq!(cards, [4u8; 9], "{:?} does not pass validation", self);
+        let expected = (0..13).map(|v| match ranks.contains(&v) {
+            true => rules.copies,
+            false => 0,
+        });
+        cards.iter().copied().eq(expected)
     }
 
     /// How close (approx) this state is to a solution.
-    fn score(&self) -> u8 {
+    fn score(&self, rules: &Rules, h: &Heuristic) -> u8 {
         let mut max_span_len = 0;
         let mut free_cell_len = 0;
         let mut score: u8 = self
             .0
+            .as_ref()
             .iter()
             .map(|c| {
                 if !c.is_dead() {
@@ -436,168 +1698,1720 @@ impl State {
                         free_cell_len += 1;
                     }
                 }
-                let free_cell_score = if c.1.is_some() { 0 } else { 1 };
-                // let free_stack_score = if c.is_empty() { 5 } else { 0 };
-                // let dead_score = if c.is_dead() { 0 } else { 5 };
-                let tidy_score = 6 - c.0.len();
-                tidy_score + free_cell_score //+ free_stack_score + dead_score
+                let free_cell_score = if c.1.is_some() { 0 } else { h.free_slot };
+                let free_stack_score = if c.is_empty() { h.empty_column } else { 0 };
+                let dead_score = if c.is_dead() { h.complete } else { 0 };
+                let tidy_score = (6 - c.0.len()).saturating_mul(h.tidy);
+                tidy_score
+                    .saturating_add(free_cell_score)
+                    .saturating_add(free_stack_score)
+                    .saturating_add(dead_score)
             })
-            .sum();
-        if max_span_len > free_cell_len {
+            .fold(0, u8::saturating_add);
+        // Without slots, spans move whole or onto other spans only.
+        if rules.slots && max_span_len > free_cell_len {
             // Penalty for not able to move.
-            let penalty = (max_span_len - free_cell_len) * 10;
+            let penalty = (max_span_len - free_cell_len).saturating_mul(h.stuck);
             score -= score.min(penalty);
         }
         score
     }
 
+    /// Lower bound of the moves to solve. Each span and slot card has to be
+    /// moved at least once, except a span at the bottom of a column that can
+    /// stay to be completed: from the highest rank if a run takes all ranks.
+    /// A move takes cards from only one of them.
+    fn min_moves(&self, rules: &Rules) -> u8 {
+        let all_ranks = rules.run_len == rules.rank_count();
+        self.0
+            .as_ref()
+            .iter()
+            .map(|c| {
+                let anchored =
+                    c.0.as_ref()
+                        .first()
+                        .is_some_and(|s| !all_ranks || s.top() == rules.highest);
+                c.0.len() - anchored as u8 + c.1.is_some() as u8
+            })
+            .sum()
+    }
+
     fn find_column_index(&self, col: &Column, exclude: Option<u8>) -> u8 {
-        for i in 0..6 {
-            if &self.0[i] == col && Some(i as u8) != exclude {
-                return i as u8;
+        for i in 0..self.0.len() {
+            if &self.0[i] == col && Some(i) != exclude {
+                return i;
             }
         }
         panic!("cannot find column {:?} in {:?}", col, self);
     }
+
+    /// Append the compact encoding to `out`. Per column, a header byte (span
+    /// count in bits 0-2, or 7 for a complete column of 1 span, whether the
+    /// slot is used in bit 3, the slot card in bits 4-7), then a byte per span
+    /// (top card in bits 4-7, length in bits 0-3). Usually 20 to 30 bytes.
+    fn pack(&self, out: &mut Vec<u8>) {
+        for c in self.0.as_ref() {
+            let slot = match c.1 {
+                Some(card) => 8 | (card.0 << 4),
+                None => 0,
+            };
+            let span_count = if c.2 { 7 } else { c.0.len() };
+            out.push(span_count | slot);
+            out.extend(c.0.as_ref().iter().map(|s| (s.top().0 << 4) | s.len()));
+        }
+    }
+
+    /// Decode [`State::pack`] of a normalized state of `rules`, if `bytes`
+    /// is one.
+    fn unpack_checked(bytes: &[u8], rules: &Rules) -> Option<State> {
+        let mut rest = bytes;
+        for _ in 0..rules.columns {
+            let (&header, tail) = or!(rest.split_first(), break);
+            let span_count = match header & 7 {
+                7 => 1,
+                n => n as usize,
+            };
+            rest = tail.get(span_count..)?;
+        }
+        if !rest.is_empty() {
+            return None;
+        }
+        let state = State::unpack(bytes);
+        let columns_valid = state.0.as_ref().iter().all(|c| {
+            (!c.2 || rules.is_complete(c))
+                && (rules.slots || c.1.is_none())
+                && c.0.as_ref().iter().all(|s| s.top().0 < 13)
+        });
+        if !columns_valid || !state.is_valid(rules) {
+            return None;
+        }
+        let mut packed = Vec::new();
+        state.pack(&mut packed);
+        let mut normalized = state;
+        normalized.normalize(rules);
+        (packed == bytes && normalized == state).then_some(state)
+    }
+
+    /// Decode [`State::pack`].
+    fn unpack(mut bytes: &[u8]) -> State {
+        let mut state = State::default();
+        while let Some(&header) = bytes.first() {
+            let mut c = Column::default();
+            if header & 8 != 0 {
+                c.1 = Some(Card(header >> 4));
+            }
+            let span_count = match header & 7 {
+                7 => {
+                    c.2 = true;
+                    1
+                }
+                n => n as usize,
+            };
+            for &b in &bytes[1..=span_count] {
+                c.0.push(Span(Card(b >> 4), b & 15));
+            }
+            state.0.push(c);
+            bytes = &bytes[1 + span_count..];
+        }
+        state
+    }
+}
+
+/// States explored to prove a state [`Deadlock::Sealed`].
+const SEALED_STATES: usize = 64;
+
+/// Why a state cannot be solved, found before it is searched.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Deadlock {
+    /// No card can move: every slot is full, or no top card fits elsewhere.
+    Blocked,
+    /// A few moves lead only to each other, none of them empties a column,
+    /// and none solves the deal.
+    Sealed,
+}
+
+impl Deadlock {
+    pub const ALL: [Deadlock; 2] = [Deadlock::Blocked, Deadlock::Sealed];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Deadlock::Blocked => "blocked",
+            Deadlock::Sealed => "sealed",
+        }
+    }
 }
 
+impl State {
+    /// The columns of each legal move, from and to. Like
+    /// [`State::next_states_by_moving`], without building the states.
+    fn moves<'a>(&'a self, rules: &'a Rules) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let columns = self.0.as_ref();
+        columns.iter().enumerate().flat_map(move |(i, c1)| {
+            let span1 = c1.movable_span();
+            columns
+                .iter()
+                .enumerate()
+                .filter(move |&(j, c2)| {
+                    let span1 = or!(span1, return false);
+                    if i == j || c2.is_dead() || c2.card_slot().is_some() {
+                        return false;
+                    }
+                    match c2.movable_span() {
+                        Some(span2) => {
+                            span2.accept_span_size(span1) > 0
+                                || rules.slots && c1.card_slot().is_none()
+                        }
+                        None => true,
+                    }
+                })
+                .map(move |(j, _)| (i, j))
+        })
+    }
+
+    /// All states reachable from this one, if it has a single move, they are
+    /// at most `SEALED_STATES`, and none of them is solved. Gives up at the
+    /// first empty column or free slot, since almost any card can move there.
+    fn sealed_pocket(&self, rules: &Rules) -> Option<Vec<State>> {
+        let escapes = |s: &State| {
+            s.is_success()
+                || s.0
+                    .as_ref()
+                    .iter()
+                    .any(|c| c.is_empty() || rules.slots && !c.is_dead() && c.card_slot().is_none())
+        };
+        if escapes(self) || self.moves(rules).nth(1).is_some() {
+            return None;
+        }
+        let mut seen = vec![*self];
+        let mut to_visit = vec![*self];
+        while let Some(state) = to_visit.pop() {
+            for next_state in state.distinct_next_states(rules) {
+                if seen.contains(&next_state) {
+                    continue;
+                }
+                if escapes(&next_state) || seen.len() == SEALED_STATES {
+                    return None;
+                }
+                seen.push(next_state);
+                to_visit.push(next_state);
+            }
+        }
+        Some(seen)
+    }
+}
+
+/// Dense id of a normalized state, in the order they are found.
+type Id = u32;
+
+const NO_PARENT: Id = Id::MAX;
+
 /// Brute force searching all states.
 #[derive(Default)]
 struct Searcher {
-    // Assigned states.
-    cache: HashMap<State, usize>,
-    states: Vec<State>,
-    state_step_count: Vec<u16>,
+    // Assigned states, packed. Indexed by id, like the vectors below.
+    states: Interner,
+    state_step_count: SpillVec<u16>,
 
-    // State K comes from State V.0 with step V.1.
-    edges: HashMap<usize, (usize, TransferStep)>,
+    // State i is reached from state `parent.get(i)`.
+    parent: SpillVec<Id>,
 
     // Visited states.
-    visited: HashSet<usize>,
+    visited: BitSet,
+
+    // States found lost by a deadlock detector. Never searched.
+    dead: BitSet,
+    // States of the sealed pockets found so far.
+    sealed: HashSet<State>,
+
+    // Reused buffer of `assign_id`.
+    packed: Vec<u8>,
 
     // For progress rendering.
     best_score: u8,
-    best_state_id: usize,
+    best_state_id: Id,
+
+    // Stats.
+    cache_hit_count: usize,
+    pruned: [usize; Deadlock::ALL.len()],
+
+    // Set if a limit is reached.
+    gave_up: Option<GaveUp>,
+
+    // Config
+    threads: usize,
+    deterministic: bool,
+    exhaustive: bool,
+    rules: Rules,
+    heuristic: Heuristic,
+    tablebase: Option<Arc<Tablebase>>,
+    prune: bool,
 }
 
 impl Searcher {
-    fn to_id(&mut self, state: &State, step_count: u16) -> usize {
-        if let Some(id) = self.cache.get(state) {
-            return *id;
-        }
-        let id = self.states.len();
-        self.states.push(*state);
-        self.state_step_count.push(step_count);
-        self.cache.insert(*state, id);
-        if (id + 1000_001) % 1000_000 == 0 {
-            eprint!(
-                "State count: {}M. Best score: {} {:?}          \r",
-                1 + (id / 1000_000),
-                self.best_score,
-                &self.states[self.best_state_id]
-            );
+    /// Keep the states, their step counts and parents in temporary files
+    /// in `dir`. Before any state is assigned.
+    fn spill_to(&mut self, dir: &Path) -> io::Result<()> {
+        let states = Interner::spill_to(dir)?;
+        let state_step_count = SpillVec::spill_to(dir)?;
+        self.parent = SpillVec::spill_to(dir)?;
+        self.states = states;
+        self.state_step_count = state_step_count;
+        Ok(())
+    }
+
+    /// Return the id of `state`, and whether it is new. New states start
+    /// with `step_count` and no parent.
+    fn assign_id(&mut self, state: &State, step_count: u16) -> (Id, bool) {
+        self.assign_ids([state].into_iter(), step_count)[0]
+    }
+
+    /// [`Searcher::assign_id`] of each state, looked up together.
+    fn assign_ids<'a>(
+        &mut self,
+        states: impl Iterator<Item = &'a State>,
+        step_count: u16,
+    ) -> Vec<(Id, bool)> {
+        self.packed.clear();
+        let mut ends = Vec::new();
+        for state in states {
+            state.pack(&mut self.packed);
+            ends.push(self.packed.len());
+        }
+        let keys: Vec<&[u8]> = ends
+            .iter()
+            .scan(0, |start, &end| {
+                Some(&self.packed[std::mem::replace(start, end)..end])
+            })
+            .collect();
+        let ids = self.states.insert_many(&keys);
+        for &(_, is_new) in &ids {
+            if is_new {
+                self.state_step_count.push(step_count);
+                self.parent.push(NO_PARENT);
+            } else {
+                self.cache_hit_count += 1;
+            }
+        }
+        ids
+    }
+
+    fn state(&self, id: Id) -> State {
+        State::unpack(&self.states.get(id))
+    }
+
+    /// Mark a new state dead if a deadlock detector finds it lost. Counts
+    /// the first detector that does.
+    fn prune(&mut self, id: Id, state: &State) {
+        if !self.prune || state.is_success() {
+            return;
         }
-        id
+        let deadlock = if state.moves(&self.rules).next().is_none() {
+            Deadlock::Blocked
+        } else if self.sealed.contains(state) {
+            Deadlock::Sealed
+        } else if let Some(pocket) = state.sealed_pocket(&self.rules) {
+            self.sealed.extend(pocket);
+            Deadlock::Sealed
+        } else {
+            return;
+        };
+        trace!("  Pruned as {}", deadlock.name());
+        self.pruned[deadlock as usize] += 1;
+        self.dead.insert(id as usize);
+    }
+
+    /// Approximate memory used by the searcher, in bytes.
+    fn memory_usage(&self, heap_len: usize) -> usize {
+        self.states.memory_usage()
+            + self.state_step_count.memory_usage()
+            + self.parent.memory_usage()
+            + self.visited.memory_usage()
+            + self.dead.memory_usage()
+            + heap_len * size_of::<((u8, i16, u8), Id)>()
     }
 
-    /// Return Some(explain) if a solution is found.
-    fn search(&mut self, initial_state: State) -> Option<String> {
+    /// Return Some(moves) if a solution is found.
+    /// Set `gave_up` if a limit is reached.
+    fn search(&mut self, initial_state: State, limits: &Limits) -> Option<Vec<Move>> {
+        let mut budget = Budget::new(limits);
         // key: (score, -step_count, step_score)
         let mut to_visit = {
             let mut state = initial_state;
-            state.normalize();
-            let score = state.score();
-            let id = self.to_id(&state, 0);
+            state.normalize(&self.rules);
+            let score = state.score(&self.rules, &self.heuristic);
+            let (id, _) = self.assign_id(&state, 0);
             let mut heap = BinaryHeap::new();
             heap.push(((score, 0i16, 0u8), id));
             heap
         };
+        if let Some(tablebase) = &self.tablebase {
+            let mut state = initial_state;
+            state.normalize(&self.rules);
+            if tablebase.distance(&state).is_some() {
+                info!("Started in the tablebase");
+                return Some(tablebase.line(initial_state, &self.heuristic));
+            }
+        }
         let mut result = None;
-        while let Some(((score1, _neg_step_count, _step_score1), id1)) = to_visit.pop() {
-            if !self.visited.insert(id1) {
-                continue;
+        let exact_order = self.deterministic;
+        let is_visited = |this: &Self, _key, id| this.visited.contains(id as usize);
+        self.run_batches(
+            &mut to_visit,
+            exact_order,
+            is_visited,
+            |this, to_visit, key, id1, next_states| {
+                let (score1, _neg_step_count, _step_score1) = key;
+                this.visited.insert(id1 as usize);
+                let memory = this.memory_usage(to_visit.len());
+                if let Err(reason) = budget.check(this.states.len(), memory) {
+                    this.gave_up = Some(reason);
+                    return ControlFlow::Break(());
+                }
+                let step_count1 = this.state_step_count.get(id1 as usize);
+                budget.report(this.states.len(), |p| {
+                    p.best_score = Some(this.best_score as usize);
+                    p.depth = Some(step_count1 as usize);
+                    p.frontier = Some(to_visit.len());
+                });
+                let state1 = &this.state(id1);
+                debug!("Considering {:?} Score {}", &state1, score1);
+                if score1 > this.best_score {
+                    this.best_score = score1;
+                    this.best_state_id = id1;
+                }
+                if state1.is_success() && result.is_none() {
+                    info!("Found solution!");
+                    result = Some(this.explain_solution(initial_state, id1));
+                    if !this.exhaustive {
+                        return ControlFlow::Break(());
+                    }
+                }
+                let step_count2 = step_count1 + 1;
+                let ids = this.assign_ids(next_states.iter().map(|(s, _)| s), step_count2);
+                for ((next_state, step), (id2, is_new)) in next_states.into_iter().zip(ids) {
+                    let i2 = id2 as usize;
+                    if is_new {
+                        this.prune(id2, &next_state);
+                    }
+                    if this.dead.contains(i2) {
+                        continue;
+                    }
+                    if !this.visited.contains(i2) {
+                        trace!(" Next: {:?}", &next_state);
+                        let score2 = next_state.score(&this.rules, &this.heuristic);
+                        to_visit.push(((score2, -(step_count2 as i16), step.step_score), id2));
+                    }
+                    // Keep the path with less steps. Parents then always have
+                    // less steps than their children, so there are no cycles.
+                    let existing_step_count2 = this.state_step_count.get(i2);
+                    if is_new || existing_step_count2 > step_count2 {
+                        if !is_new {
+                            trace!(
+                                "  Optimize step count {} -> {}",
+                                existing_step_count2,
+                                step_count2,
+                            );
+                        }
+                        this.state_step_count.set(i2, step_count2);
+                        this.parent.set(i2, id1);
+                    }
+                    if let Some(tablebase) = &this.tablebase {
+                        if let Some(distance) = tablebase.distance(&next_state) {
+                            info!("Reached the tablebase, {} moves left", distance);
+                            let mut moves = this.explain_solution(initial_state, id2);
+                            let end = moves.last().map_or(initial_state, |m| m.board);
+                            moves.extend(tablebase.line(end, &this.heuristic));
+                            result = Some(moves);
+                            return ControlFlow::Break(());
+                        }
+                    }
+                }
+                ControlFlow::Continue(())
+            },
+        );
+        result
+    }
+
+    /// A* search on the step count, using [`State::min_moves`] as the bound.
+    /// Return the fewest moves if a solution is found.
+    /// Set `gave_up` if a limit is reached.
+    fn search_optimal(&mut self, initial_state: State, limits: &Limits) -> Option<Vec<Move>> {
+        let mut budget = Budget::new(limits);
+        // key: (-(step_count + bound), step_count)
+        let mut to_visit = {
+            let mut state = initial_state;
+            state.normalize(&self.rules);
+            let (id, _) = self.assign_id(&state, 0);
+            self.best_score = u8::MAX - state.min_moves(&self.rules);
+            let mut heap = BinaryHeap::new();
+            heap.push(((-(state.min_moves(&self.rules) as i16), 0u16), id));
+            heap
+        };
+        let mut result = None;
+        // Skip outdated entries. A shorter path was found later.
+        let is_stale = |this: &Self, (_neg_cost, step_count), id| {
+            step_count != this.state_step_count.get(id as usize)
+                || this.visited.contains(id as usize)
+        };
+        let exact_order = self.deterministic;
+        self.run_batches(
+            &mut to_visit,
+            exact_order,
+            is_stale,
+            |this, to_visit, key, id1, next_states| {
+                let (_neg_cost, step_count1) = key;
+                this.visited.insert(id1 as usize);
+                let memory = this.memory_usage(to_visit.len());
+                if let Err(reason) = budget.check(this.states.len(), memory) {
+                    this.gave_up = Some(reason);
+                    return ControlFlow::Break(());
+                }
+                budget.report(this.states.len(), |p| {
+                    p.best_score = Some(this.best_score as usize);
+                    p.depth = Some(step_count1 as usize);
+                    p.frontier = Some(to_visit.len());
+                });
+                let state1 = this.state(id1);
+                let score1 = u8::MAX - state1.min_moves(&this.rules);
+                if score1 > this.best_score {
+                    this.best_score = score1;
+                    this.best_state_id = id1;
+                }
+                if state1.is_success() && result.is_none() {
+                    // Out of order, a state with a lower bound may still be waiting.
+                    if to_visit.peek().is_some_and(|(top, _)| top.0 > key.0) {
+                        this.visited.remove(id1 as usize);
+                        to_visit.push((key, id1));
+                        return ControlFlow::Continue(());
+                    }
+                    info!("Found solution in {} steps", step_count1);
+                    result = Some(this.explain_solution(initial_state, id1));
+                    if !this.exhaustive {
+                        return ControlFlow::Break(());
+                    }
+                }
+                let step_count2 = step_count1 + 1;
+                let ids = this.assign_ids(next_states.iter().map(|(s, _)| s), step_count2);
+                for ((next_state, _step), (id2, is_new)) in next_states.into_iter().zip(ids) {
+                    let i2 = id2 as usize;
+                    if is_new {
+                        this.prune(id2, &next_state);
+                    }
+                    if this.dead.contains(i2) {
+                        continue;
+                    }
+                    if !is_new && this.state_step_count.get(i2) <= step_count2 {
+                        continue;
+                    }
+                    // The bound is consistent, so in exact order visited states
+                    // already have their shortest paths. Otherwise visit again.
+                    this.visited.remove(i2);
+                    this.state_step_count.set(i2, step_count2);
+                    this.parent.set(i2, id1);
+                    let cost = step_count2 as i16 + next_state.min_moves(&this.rules) as i16;
+                    to_visit.push(((-cost, step_count2), id2));
+                }
+                ControlFlow::Continue(())
+            },
+        );
+        result
+    }
+
+    /// Pop states from `to_visit` in batches, expand them to their normalized
+    /// next states on `threads` threads, and pass them to `visit`.
+    ///
+    /// Entries that are `skip`ped when popped or when their turn comes are
+    /// ignored. With `exact_order`, the rest of a batch is put back once
+    /// `visit` pushes a better entry, so states are visited in the same order
+    /// as with a single thread. Otherwise the whole batch is visited.
+    fn run_batches<K: Ord + Copy>(
+        &mut self,
+        to_visit: &mut BinaryHeap<(K, Id)>,
+        exact_order: bool,
+        skip: impl Fn(&Self, K, Id) -> bool,
+        mut visit: impl FnMut(
+            &mut Self,
+            &mut BinaryHeap<(K, Id)>,
+            K,
+            Id,
+            Vec<(State, TransferStep)>,
+        ) -> ControlFlow<()>,
+    ) {
+        fn expand(state: &State, rules: &Rules) -> Vec<(State, TransferStep)> {
+            let mut result: Vec<_> = state.next_states(rules).collect();
+            for (next_state, _) in &mut result {
+                next_state.normalize(rules);
+            }
+            result
+        }
+
+        let rules = &self.rules.clone();
+        let threads = self.threads.max(1);
+        let batch_size = if threads == 1 { 1 } else { threads * 8 };
+        // Jobs are chunks of a batch: (chunk index, states).
+        let (job_sender, job_receiver) = mpsc::channel::<(usize, Vec<State>)>();
+        let job_receiver = Mutex::new(job_receiver);
+        let (result_sender, result_receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            for _ in 1..threads {
+                let result_sender = result_sender.clone();
+                let job_receiver = &job_receiver;
+                scope.spawn(move || loop {
+                    let job = job_receiver.lock().unwrap().recv();
+                    let (i, states) = or!(job.ok(), break);
+                    let next_states = states.iter().map(|s| expand(s, rules)).collect();
+                    let _ = result_sender.send((i, next_states));
+                });
+            }
+
+            let mut batch = Vec::with_capacity(batch_size);
+            let mut expanded = Vec::with_capacity(batch_size);
+            'search: loop {
+                batch.clear();
+                while batch.len() < batch_size {
+                    let (key, id) = or!(to_visit.pop(), break);
+                    if !skip(self, key, id) {
+                        batch.push((key, id));
+                    }
+                }
+                if batch.is_empty() {
+                    break 'search;
+                }
+
+                // Expand the first chunk on this thread, the others on workers.
+                let chunk_size = batch.len().div_ceil(threads);
+                let mut chunks: Vec<Vec<_>> =
+                    batch.chunks(chunk_size).map(|_| Vec::new()).collect();
+                for (i, chunk) in batch.chunks(chunk_size).enumerate().skip(1) {
+                    let states = chunk.iter().map(|&(_, id)| self.state(id)).collect();
+                    job_sender.send((i, states)).unwrap();
+                }
+                let first_chunk = &batch[..chunk_size.min(batch.len())];
+                chunks[0] = first_chunk
+                    .iter()
+                    .map(|&(_, id)| expand(&self.state(id), rules))
+                    .collect();
+                for _ in 1..chunks.len() {
+                    let (i, next_states) = result_receiver.recv().unwrap();
+                    chunks[i] = next_states;
+                }
+                expanded.clear();
+                expanded.extend(chunks.into_iter().flatten());
+
+                for (i, next_states) in expanded.drain(..).enumerate() {
+                    let (key, id) = batch[i];
+                    if exact_order && to_visit.peek().is_some_and(|top| top > &(key, id)) {
+                        to_visit.extend(batch[i..].iter().copied());
+                        break;
+                    }
+                    if i > 0 && skip(self, key, id) {
+                        continue;
+                    }
+                    if visit(self, to_visit, key, id, next_states).is_break() {
+                        break 'search;
+                    }
+                }
+            }
+            // Stop the workers. The scope waits for them.
+            drop(job_sender);
+        });
+    }
+
+    /// Explain how to get the solution state.
+    fn explain_solution(&self, initial_state: State, end_state_id: Id) -> Vec<Move> {
+        self.explain_path(initial_state, &self.path_to(end_state_id))
+    }
+
+    /// Moves along a path of normalized states starting from `initial_state`.
+    fn explain_path(&self, initial_state: State, path: &[State]) -> Vec<Move> {
+        let rules = &self.rules;
+        // Only states are stored. Find a step between each pair, and replay
+        // the steps to reconstruct the column numbers.
+        let mut state = initial_state;
+        let mut moves = Vec::with_capacity(path.len().saturating_sub(1));
+        for pair in path.windows(2) {
+            let step = pair[0]
+                .next_states(rules)
+                .find_map(|(mut next_state, step)| {
+                    next_state.normalize(rules);
+                    (next_state == pair[1]).then_some(step)
+                })
+                .expect("parent state leads to its child");
+            let mut m = step.explain(&state);
+            step.apply(&mut state, rules);
+            m.board = state;
+            m.board_score = state.score(rules, &self.heuristic);
+            moves.push(m);
+        }
+        moves
+    }
+
+    /// Normalized states from the initial state to the given one.
+    fn path_to(&self, end_state_id: Id) -> Vec<State> {
+        let mut path = vec![self.state(end_state_id)];
+        let mut id = end_state_id;
+        while self.parent.get(id as usize) != NO_PARENT {
+            let state = path.last().unwrap();
+            debug!(
+                "Score {} {:?}",
+                state.score(&self.rules, &self.heuristic),
+                state
+            );
+            id = self.parent.get(id as usize);
+            path.push(self.state(id));
+        }
+        path.reverse();
+        path
+    }
+}
+
+/// Shorten a solution path of normalized states. A breadth first search
+/// from each state looks for a shorter way to a later state on the path, or
+/// to any solved state. The search depth grows until no shortcut is left
+/// or `deadline` passes.
+fn shorten_path(
+    mut path: Vec<State>,
+    rules: &Rules,
+    deadline: Instant,
+    cancel: &CancelHandle,
+) -> Vec<State> {
+    let mut expanded = 0usize;
+    let mut out_of_time = || {
+        expanded += 1;
+        expanded.is_multiple_of(64) && (Instant::now() >= deadline || cancel.is_cancelled())
+    };
+    let mut depth = 2;
+    let mut i = 0;
+    let mut improved = false;
+    while depth < path.len() {
+        if i + 2 >= path.len() {
+            // End of a pass. Search deeper once no shortcut is found.
+            if !improved {
+                depth += 1;
+                debug!("Shortening with depth {}", depth);
             }
-            let step_count1 = self.state_step_count[id1];
-            let state1 = &self.states[id1];
-            dprintln!("Considering {:?} Score {}", &state1, score1);
-            if score1 > self.best_score {
-                self.best_score = score1;
-                self.best_state_id = id1;
+            i = 0;
+            improved = false;
+            continue;
+        }
+        let index: HashMap<State, usize> = path.iter().copied().zip(0..).skip(i + 2).collect();
+        // State to the state it is reached from, and the current layer.
+        let mut parent = HashMap::from([(path[i], path[i])]);
+        let mut layer = vec![path[i]];
+        let mut shortcut = None;
+        for d in 1..depth {
+            let mut next_layer = Vec::new();
+            for state in &layer {
+                if out_of_time() {
+                    return path;
+                }
+                for (mut next_state, _) in state.next_states(rules) {
+                    next_state.normalize(rules);
+                    if parent.contains_key(&next_state) {
+                        continue;
+                    }
+                    parent.insert(next_state, *state);
+                    next_layer.push(next_state);
+                }
             }
-            if state1.is_success() {
-                eprintln!("\nFound solution!");
-                result = Some(self.explain_solution(initial_state, id1));
+            let last = path.len() - 1;
+            let best = next_layer
+                .iter()
+                .filter_map(|s| match index.get(s) {
+                    _ if s.is_success() => Some((last, *s)),
+                    Some(&j) => Some((j, *s)),
+                    None => None,
+                })
+                .max_by_key(|&(j, _)| j);
+            if let Some((j, end)) = best.filter(|&(j, _)| j > i + d) {
+                shortcut = Some((j, end));
                 break;
             }
-            for (mut next_state, step) in state1.clone().next_states() {
-                next_state.normalize();
-                let step_count2 = step_count1 + 1;
-                let id2 = self.to_id(&next_state, step_count2);
-                if !self.visited.contains(&id2) {
-                    dprintln!(" Next: {:?}", &next_state);
-                    let score2 = next_state.score();
-                    to_visit.push(((score2, -(step_count2 as i16), step.step_score), id2));
-                    self.edges.insert(id2, (id1, step));
-                } else {
-                    // Use less steps?
-                    let existing_step_count2 = self.state_step_count[id2];
-                    if existing_step_count2 > step_count2 {
-                        dprintln!(" Visited Next: {:?}", &next_state);
-                        dprintln!(
-                            "  Optimize step count {} -> {}",
-                            existing_step_count2,
-                            step_count2,
-                        );
-                        self.state_step_count[id2] = step_count2;
-                        self.edges.insert(id2, (id1, step));
+            layer = next_layer;
+        }
+        match shortcut {
+            Some((j, end)) => {
+                let mut between = vec![end];
+                while let Some(&s) = parent.get(between.last().unwrap()) {
+                    if s == path[i] {
+                        break;
                     }
+                    between.push(s);
                 }
+                between.reverse();
+                debug!(
+                    "Shortcut from step {} to {} in {} steps",
+                    i,
+                    j,
+                    between.len()
+                );
+                path.splice(i + 1..=j, between);
+                improved = true;
             }
+            None => i += 1,
         }
-        eprintln!("State count: {}", self.states.len());
-        result
     }
+    path
+}
 
-    /// Explain how to get the solution state.
-    fn explain_solution(&self, initial_state: State, end_state_id: usize) -> String {
-        let mut steps = Vec::new();
-        let mut msg = String::new();
-        let mut id = end_state_id;
-        while let Some((prev_id, step)) = self.edges.get(&id) {
-            let state = &self.states[id];
-            dprintln!("Score {} {:?}", state.score(), state);
-            steps.push(step);
-            id = *prev_id;
-        }
-        steps.reverse();
-        // Replay steps to reconstruct the column numbers.
-        let mut state = initial_state;
-        for (i, step) in steps.into_iter().enumerate() {
-            msg += &format!("Step {:>3}. {}\n", i + 1, step.explain(&state));
-            step.apply(&mut state);
-            if is_verbose() {
-                msg += &format!("          Board: {} (Score: {})\n", &state, state.score());
+/// Winning positions of the endgame, and their distances to a solution.
+///
+/// An endgame position has one fewer complete columns than copies of each
+/// rank, a column with a run from the highest rank at the bottom, and at
+/// most `loose` other cards. The distances are found by retrograde analysis
+/// and only count moves between such positions, so a line from the table is
+/// not always the shortest.
+pub struct Tablebase {
+    rules: Rules,
+    loose: u8,
+    // Normalized winning positions, packed, and their distances.
+    states: Interner,
+    distances: Vec<u8>,
+}
+
+const TABLEBASE_MAGIC: &str = "cjul tablebase 1";
+
+impl Tablebase {
+    /// Find all winning endgame positions with up to `loose` cards outside
+    /// the complete columns and the anchored run. Needs runs of all ranks.
+    pub fn build(rules: &Rules, loose: u8) -> Result<Tablebase, String> {
+        Tablebase::check(rules, loose)?;
+        let positions = endgame_positions(rules, loose);
+        info!("{} endgame positions", positions.len());
+
+        // Distances to a solution, 0 if not known to win. Predecessors of
+        // each position within the endgame.
+        let n = positions.len();
+        let mut distances = vec![0u8; n];
+        let mut predecessors = vec![Vec::new(); n];
+        let mut to_visit = VecDeque::new();
+        let mut packed = Vec::new();
+        for (id, distance) in distances.iter_mut().enumerate() {
+            let state = State::unpack(&positions.get(id as Id));
+            for next_state in state.distinct_next_states(rules) {
+                if next_state.is_success() {
+                    if *distance == 0 {
+                        *distance = 1;
+                        to_visit.push_back(id);
+                    }
+                    continue;
+                }
+                if loose_cards(&next_state, rules).is_some_and(|l| l <= loose) {
+                    packed.clear();
+                    next_state.pack(&mut packed);
+                    if let Some(next_id) = positions.find(&packed) {
+                        predecessors[next_id as usize].push(id as Id);
+                    }
+                }
+            }
+        }
+        while let Some(id) = to_visit.pop_front() {
+            for &prev in &predecessors[id] {
+                if distances[prev as usize] == 0 {
+                    let distance = distances[id].checked_add(1).ok_or_else(|| {
+                        "some endgames take more than 255 moves, use fewer loose cards".to_string()
+                    })?;
+                    distances[prev as usize] = distance;
+                    to_visit.push_back(prev as usize);
+                }
+            }
+        }
+
+        let mut table = Tablebase {
+            rules: rules.clone(),
+            loose,
+            states: Interner::default(),
+            distances: Vec::new(),
+        };
+        for (id, &distance) in distances.iter().enumerate() {
+            if distance > 0 {
+                table.states.insert(&positions.get(id as Id));
+                table.distances.push(distance);
+            }
+        }
+        info!("{} winning positions", table.len());
+        Ok(table)
+    }
+
+    fn check(rules: &Rules, loose: u8) -> Result<(), String> {
+        rules.validate()?;
+        if rules.run_len != rules.rank_count() {
+            return Err("a tablebase needs runs of all ranks".to_string());
+        }
+        if !(1..rules.rank_count()).contains(&loose) {
+            return Err(format!(
+                "loose cards must be 1 to {}",
+                rules.rank_count() - 1
+            ));
+        }
+        if rules.columns < rules.copies {
+            return Err("a tablebase needs a column per copy".to_string());
+        }
+        Ok(())
+    }
+
+    /// Number of winning positions.
+    pub fn len(&self) -> usize {
+        self.distances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.distances.is_empty()
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// Most loose cards of a position.
+    pub fn loose(&self) -> u8 {
+        self.loose
+    }
+
+    /// Moves of the longest line.
+    pub fn longest(&self) -> u8 {
+        self.distances.iter().copied().max().unwrap_or(0)
+    }
+
+    /// The file format: a text header with the rules, then per position its
+    /// packed length, the packed state and the distance.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header = format!("{}\n{}\n{}\n", TABLEBASE_MAGIC, self.rules, self.loose);
+        let mut out = header.into_bytes();
+        for (id, &distance) in self.distances.iter().enumerate() {
+            let packed = self.states.get(id as Id);
+            out.push(packed.len() as u8);
+            out.extend_from_slice(&packed);
+            out.push(distance);
+        }
+        out
+    }
+
+    /// Parse [`Tablebase::to_bytes`]. Checks that each entry is an endgame
+    /// position of the rules, with a move to one a move closer to a solution.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Tablebase, String> {
+        let invalid = || "not a cjul tablebase".to_string();
+        let mut fields = bytes.splitn(4, |&b| b == b'\n');
+        let mut text = || {
+            let field = fields.next().ok_or_else(invalid)?;
+            std::str::from_utf8(field).map_err(|_| invalid())
+        };
+        if text()? != TABLEBASE_MAGIC {
+            return Err(invalid());
+        }
+        let rules: Rules = text()?.parse()?;
+        let loose = text()?.parse().map_err(|_| invalid())?;
+        Tablebase::check(&rules, loose)?;
+        let mut table = Tablebase {
+            rules,
+            loose,
+            states: Interner::default(),
+            distances: Vec::new(),
+        };
+        let mut rest = fields.next().ok_or_else(invalid)?;
+        while let Some((&len, tail)) = rest.split_first() {
+            let len = len as usize;
+            if tail.len() <= len {
+                return Err("truncated tablebase".to_string());
+            }
+            let state = State::unpack_checked(&tail[..len], &table.rules).ok_or_else(invalid)?;
+            let endgame = loose_cards(&state, &table.rules).is_some_and(|l| l <= loose);
+            if !endgame || state.is_success() || !table.states.insert(&tail[..len]).1 {
+                return Err(invalid());
+            }
+            table.distances.push(tail[len]);
+            rest = &tail[len + 1..];
+        }
+        if !table.is_consistent() {
+            return Err(invalid());
+        }
+        Ok(table)
+    }
+
+    /// Whether each distance is one more than the fewest of the next
+    /// positions, counting a solution as 0.
+    fn is_consistent(&self) -> bool {
+        (0..self.len()).all(|id| {
+            let state = State::unpack(&self.states.get(id as Id));
+            let fewest = state
+                .distinct_next_states(&self.rules)
+                .iter()
+                .filter_map(|s| match s.is_success() {
+                    true => Some(0),
+                    false => self.distance(s),
+                })
+                .min();
+            fewest.and_then(|d| d.checked_add(1)) == Some(self.distances[id])
+        })
+    }
+
+    /// Moves to a solution from a normalized `state`, if it is in the table.
+    fn distance(&self, state: &State) -> Option<u8> {
+        if loose_cards(state, &self.rules)? > self.loose {
+            return None;
+        }
+        let mut packed = Vec::new();
+        state.pack(&mut packed);
+        let id = self.states.find(&packed)?;
+        Some(self.distances[id as usize])
+    }
+
+    /// Winning moves from `state`, which is in the table once normalized.
+    fn line(&self, mut state: State, heuristic: &Heuristic) -> Vec<Move> {
+        let rules = &self.rules;
+        let mut moves = Vec::new();
+        while !state.is_success() {
+            let mut normalized = state;
+            normalized.normalize(rules);
+            let distance = self.distance(&normalized).expect("line stays in the table");
+            let step = state
+                .next_states(rules)
+                .find_map(|(mut next_state, step)| {
+                    next_state.normalize(rules);
+                    let closer =
+                        next_state.is_success() || self.distance(&next_state) == Some(distance - 1);
+                    closer.then_some(step)
+                })
+                .expect("a winning position has a move closer to a solution");
+            let mut m = step.explain(&state);
+            step.apply(&mut state, rules);
+            m.board = state;
+            m.board_score = state.score(rules, heuristic);
+            moves.push(m);
+        }
+        moves
+    }
+}
+
+impl fmt::Debug for Tablebase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tablebase")
+            .field("rules", &self.rules)
+            .field("loose", &self.loose)
+            .field("positions", &self.len())
+            .finish()
+    }
+}
+
+/// Cards outside the complete columns and the run from the highest rank at
+/// the bottom of a column, if a state is an endgame position.
+fn loose_cards(state: &State, rules: &Rules) -> Option<u8> {
+    let mut dead = 0;
+    let mut cards = 0;
+    let mut anchored = None;
+    for c in state.0.as_ref() {
+        if c.is_dead() {
+            dead += 1;
+            continue;
+        }
+        cards += c.card_count();
+        if let Some(span) = c.0.as_ref().first().filter(|s| s.top() == rules.highest) {
+            anchored = Some(span.len());
+        }
+    }
+    if dead + 1 != rules.copies {
+        return None;
+    }
+    Some(cards - anchored?)
+}
+
+/// All normalized endgame positions with 1 to `loose` loose cards, except
+/// solved ones. The loose cards are the lowest ranks, put in any order on
+/// the columns that are not complete, or in their free slots.
+fn endgame_positions(rules: &Rules, loose: u8) -> Interner {
+    fn place(
+        cards: &[Card],
+        piles: &mut [Vec<Card>],
+        slots: &mut [Option<Card>],
+        use_slots: bool,
+        visit: &mut impl FnMut(&[Vec<Card>], &[Option<Card>]),
+    ) {
+        let (&card, rest) = match cards.split_first() {
+            Some(split) => split,
+            None => return visit(piles, slots),
+        };
+        for p in 0..piles.len() {
+            for i in 0..=piles[p].len() {
+                piles[p].insert(i, card);
+                place(rest, piles, slots, use_slots, visit);
+                piles[p].remove(i);
             }
         }
-        msg
+        for s in 0..slots.len() {
+            if use_slots && slots[s].is_none() {
+                slots[s] = Some(card);
+                place(rest, piles, slots, use_slots, visit);
+                slots[s] = None;
+            }
+        }
+    }
+
+    fn span_count(cards: &[Card]) -> usize {
+        let breaks = cards.windows(2).filter(|w| w[1] + 1 != w[0]).count();
+        breaks + !cards.is_empty() as usize
+    }
+
+    let run: Vec<Card> = (rules.lowest.0..=rules.highest.0).rev().map(Card).collect();
+    let dead = rules.copies as usize - 1;
+    let open = rules.columns as usize - dead;
+    let mut positions = Interner::default();
+    let mut packed = Vec::new();
+    for k in 1..=loose {
+        let cards: Vec<Card> = (0..k).map(|i| rules.lowest + i).collect();
+        // The anchored run ends above the loose cards.
+        let anchored = &run[..(rules.rank_count() - k) as usize];
+        let mut piles = vec![Vec::new(); open];
+        let mut slots = vec![None; open];
+        place(
+            &cards,
+            &mut piles,
+            &mut slots,
+            rules.slots,
+            &mut |piles, slots| {
+                let mut state = State::default();
+                for _ in 0..dead {
+                    state.0.push(Column::from_cards(&run, None, rules));
+                }
+                for (i, pile) in piles.iter().enumerate() {
+                    let mut column = if i == 0 {
+                        anchored.to_vec()
+                    } else {
+                        Vec::new()
+                    };
+                    column.extend_from_slice(pile);
+                    if span_count(&column) > 6 {
+                        return;
+                    }
+                    state.0.push(Column::from_cards(&column, slots[i], rules));
+                }
+                if state.is_success() {
+                    return;
+                }
+                state.normalize(rules);
+                packed.clear();
+                state.pack(&mut packed);
+                positions.insert(&packed);
+            },
+        );
     }
+    positions
 }
 
-pub(crate) fn process(s: &str) -> Option<String> {
-    let state = State::parse(s);
-    let mut searcher = Searcher::default();
-    searcher.search(state)
+#[test]
+fn test_parse_errors() {
+    let err = |s: &str| State::parse(s).unwrap_err().to_string();
+    let rows = [
+        "t 7 v 10 k d",
+        "9 t 10 7 d 6",
+        "8 8 6 v 10 7",
+        "t d v 8 k 10",
+        "8 d t k 9 7",
+        "9 k 9 6 6 v",
+    ];
+    assert_eq!(
+        err(&rows.join("\n").replace("10 7 d", "10 x d")),
+        "line 2, column 8: unknown card 'x'"
+    );
+    assert_eq!(
+        err(&rows.join("\n").replace("8 8 6 v", "8 8 6")),
+        "line 3: row 3 has only 5 cards"
+    );
+    assert_eq!(
+        err(&rows.join("\n").replace("8 8 6 v", "8 8 6 6 v")),
+        "line 3, column 14: row 3 has more than 6 cards"
+    );
+    assert_eq!(
+        err(&rows.join("\n").replace("t d v 8", "t d v k")),
+        "rank K appears 5 times (rows 1, 4, 4, 5, 6)"
+    );
+    assert_eq!(err(&rows[..5].join("\n")), "expected 6 rows, found 5");
 }
 
-pub(crate) fn main() {
-    use std::io::Read;
-    let s = {
-        let mut s = String::new();
-        let mut input = std::io::stdin();
-        input.read_to_string(&mut s).unwrap();
-        s
+#[test]
+fn test_notation() {
+    let names = |n| [Card(8), Card(9), Card(12)].map(|c| c.name(n));
+    assert_eq!(names(Notation::Latin), ["10", "V", "T"]);
+    assert_eq!(names(Notation::Cyrillic), ["10", "В", "Т"]);
+    assert_eq!(names(Notation::English), ["10", "J", "A"]);
+    for s in ["d", "Д", "д", "Q"] {
+        assert_eq!(Card::from_str(s), Some(Card(10)));
+    }
+    assert_eq!("English".parse(), Ok(Notation::English));
+    let latin = State::parse(INFO.example).unwrap();
+    let cyrillic = INFO
+        .example
+        .replace(['v', 'V'], "в")
+        .replace(['t', 'T'], "Т");
+    assert_eq!(State::parse(&cyrillic), Ok(latin));
+    let english = INFO
+        .example
+        .replace(['d', 'D'], "Q")
+        .replace(['t', 'T'], "a");
+    assert_eq!(State::parse(&english), Ok(latin));
+
+    use crate::{Solution as _, Solver as _};
+    let solution = latin.solve(&Options::default());
+    let options = Options {
+        notation: Notation::English,
+        ..Default::default()
+    };
+    let english = latin.solve(&options);
+    assert_eq!(
+        english.to_json().to_string(),
+        solution.to_json().to_string()
+    );
+    assert_ne!(english.to_string(), solution.to_string());
+    assert!(!english.to_string().contains(['V', 'D', 'T']));
+    assert_eq!(
+        latin.verify_with_rules(&english.to_string(), &Rules::default(), Notation::English),
+        Ok(format!("Solved in {} steps.", english.moves.unwrap().len()))
+    );
+}
+
+#[test]
+fn test_parse_position() {
+    use crate::Solution as _;
+    use crate::Solver as _;
+    let position = "\
+1: T K D V 10 9 8 7 6
+2: T K D V 10 9 8 7 6
+3: T K D V 10 9 8 7 6
+4: T K D V 10 9 8 | 6
+6: 7
+";
+    let state = State::parse(position).unwrap();
+    assert!(state.0[0].is_dead());
+    assert_eq!(state.0[3].card_slot(), Some(Card(4)));
+    assert!(state.0[4].is_empty());
+    let solution = state.solve(&Options::default());
+    assert_eq!(solution.step_count(), Some(2));
+    assert_eq!(
+        solution.next_move().unwrap(),
+        "Move [6] from 4 -> 6. [T K D V 10 9 8 6] to [7]."
+    );
+
+    let err = |s: &str| State::parse(s).unwrap_err().to_string();
+    assert_eq!(
+        err(&position.replace("6: 7", "4: 7")),
+        "line 5: column 4 is listed twice"
+    );
+    assert_eq!(
+        err(&position.replace("6: 7", "7: 7")),
+        "line 5: invalid column \"7\""
+    );
+    assert_eq!(
+        err(&position.replace("| 6", "| 6 7").replace("6: 7", "")),
+        "line 4, column 23: the slot holds only 1 card"
+    );
+    assert_eq!(
+        err(&position.replace("6: 7", "6: 7 7")),
+        "rank 7 appears 5 times, expected 4 (columns 1, 2, 3, 6, 6)"
+    );
+}
+
+#[test]
+fn test_verify() {
+    use crate::Solver as _;
+    let state = State::parse(INFO.example).unwrap();
+    let solution = state.solve(&Options::default()).to_string();
+    assert_eq!(
+        state.verify(&solution),
+        Ok("Solved in 46 steps.".to_string())
+    );
+
+    let lines: Vec<&str> = solution.lines().collect();
+    let err = state.verify(&lines[..10].join("\n")).unwrap_err();
+    assert!(err.starts_with("not solved after 10 steps"), "{}", err);
+    let err = state.verify(&lines[1..].join("\n")).unwrap_err();
+    assert!(err.starts_with("Step 1: column 1"), "{}", err);
+    let err = state.verify("Move [9] from 1 -> 1.").unwrap_err();
+    assert!(
+        err.starts_with("Step 1: cannot move [9] from 1 -> 1"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_optimal() {
+    use crate::Solver as _;
+    let path = format!("{}/data/cjul/2.in", env!("CARGO_MANIFEST_DIR"));
+    let state = State::parse(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let solution = state.solve(&Options {
+        optimal: true,
+        ..Default::default()
+    });
+    assert!(solution.proven_optimal);
+    assert_eq!(solution.moves.as_ref().unwrap().len(), 31);
+    assert_eq!(
+        state.verify(&solution.to_string()),
+        Ok("Solved in 31 steps.".to_string())
+    );
+    let greedy = state.solve(&Options::default());
+    assert!(!greedy.proven_optimal);
+    assert!(greedy.moves.unwrap().len() >= 31);
+}
+
+#[test]
+fn test_threads() {
+    use crate::Solver as _;
+    let state = State::parse(INFO.example).unwrap();
+    let single = state.solve(&Options::default()).to_string();
+    let deterministic = state.solve(&Options {
+        threads: 3,
+        deterministic: true,
+        ..Default::default()
+    });
+    assert_eq!(deterministic.to_string(), single);
+
+    let path = format!("{}/data/cjul/2.in", env!("CARGO_MANIFEST_DIR"));
+    let state = State::parse(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let solution = state.solve(&Options {
+        optimal: true,
+        threads: 3,
+        ..Default::default()
+    });
+    assert_eq!(solution.moves.as_ref().unwrap().len(), 31);
+    assert!(state.verify(&solution.to_string()).is_ok());
+
+    let single = state.solve(&Options {
+        optimal: true,
+        ..Default::default()
+    });
+    let deterministic = state.solve(&Options {
+        optimal: true,
+        threads: 3,
+        deterministic: true,
+        ..Default::default()
+    });
+    assert_eq!(deterministic.to_string(), single.to_string());
+}
+
+#[test]
+fn test_shorten() {
+    use crate::Solver as _;
+    let rules = Rules::default();
+    let state = State::parse(INFO.example).unwrap();
+    let greedy = state.solve(&Options::default()).moves.unwrap();
+    let mut path = vec![state];
+    path.extend(greedy.iter().map(|m| m.board));
+    for s in &mut path {
+        s.normalize(&rules);
+    }
+    // A detour back to the initial state.
+    let detour = [path[0], path[1]];
+    path.splice(0..0, detour);
+    let deadline = Instant::now() + Duration::from_millis(200);
+    let shorter = shorten_path(path, &rules, deadline, &CancelHandle::default());
+    assert!(shorter.len() <= greedy.len() + 1);
+    assert!(shorter.last().unwrap().is_success());
+
+    let solution = state.solve(&Options {
+        shorten: Some(Duration::from_millis(200)),
+        ..Default::default()
+    });
+    let n = solution.moves.as_ref().unwrap().len();
+    assert_eq!(solution.shortened_from, Some(greedy.len()));
+    assert!(n <= greedy.len());
+    assert_eq!(
+        state.verify(&solution.to_string()),
+        Ok(format!("Solved in {} steps.", n))
+    );
+}
+
+#[test]
+fn test_spill() {
+    use crate::Solver as _;
+    let state = State::parse(INFO.example).unwrap();
+    let in_memory = state.solve(&Options::default());
+    let spilled = state.solve(&Options {
+        spill_dir: Some(std::env::temp_dir()),
+        ..Default::default()
+    });
+    assert_eq!(spilled.to_string(), in_memory.to_string());
+    assert_eq!(spilled.stats.searched, in_memory.stats.searched);
+}
+
+#[test]
+fn test_tablebase() {
+    use crate::Solver as _;
+    let rules: Rules = "hard,columns=6,ranks=6-8".parse().unwrap();
+    assert_eq!(rules.to_string().parse(), Ok(rules));
+
+    let rules = Rules::default();
+    let tablebase = Tablebase::build(&rules, 4).unwrap();
+    assert_eq!(tablebase.len(), 699);
+    let bytes = tablebase.to_bytes();
+    let loaded = Tablebase::from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.to_bytes(), bytes);
+    let entries = format!("{}\n{}\n4\n", TABLEBASE_MAGIC, rules).len();
+    for i in [entries + 1, entries + 3, bytes.len() - 1] {
+        for change in [1, 0x20, 0x80] {
+            let mut corrupt = bytes.clone();
+            corrupt[i] ^= change;
+            assert_eq!(
+                Tablebase::from_bytes(&corrupt).err(),
+                Some("not a cjul tablebase".to_string())
+            );
+        }
+    }
+    let options = Options {
+        tablebase: Some(Arc::new(loaded)),
+        ..Default::default()
     };
-    if let Some(msg) = process(&s) {
-        println!("{}", msg);
+
+    let position = "\
+1: T K D V 10 9 8 7 6
+2: T K D V 10 9 8 7 6
+3: T K D V 10 9 8 7 6
+4: T K D V 10 7 | 6
+5: 9
+6: 8
+";
+    let state = State::parse(position).unwrap();
+    let solution = state.solve(&options);
+    assert_eq!(solution.stats.searched, 1);
+    let n = solution.moves.as_ref().unwrap().len();
+    assert_eq!(
+        state.verify(&solution.to_string()),
+        Ok(format!("Solved in {} steps.", n))
+    );
+
+    let state = State::parse(INFO.example).unwrap();
+    let solution = state.solve(&options);
+    let n = solution.moves.as_ref().unwrap().len();
+    assert_eq!(
+        state.verify(&solution.to_string()),
+        Ok(format!("Solved in {} steps.", n))
+    );
+    assert!(solution.stats.searched <= state.solve(&Options::default()).stats.searched);
+
+    let hard: Rules = "hard".parse().unwrap();
+    assert!(Tablebase::build(&hard, 4).is_ok());
+    assert!(Tablebase::build(&"run=8".parse().unwrap(), 4).is_err());
+    assert!(Tablebase::from_bytes(b"cjul tablebase 2\n").is_err());
+}
+
+#[test]
+fn test_unsolvable() {
+    use crate::Solution as _;
+    use crate::Solver as _;
+    // Put a card in the slot of every column. Then nothing can move.
+    let mut state = State::parse(INFO.example).unwrap();
+    for c in state.0.as_mut() {
+        let mut span = c.0.pop().unwrap();
+        c.1 = Some(span.bottom());
+        span.shrink_size(1);
+        if span.len() > 0 {
+            c.0.push(span);
+        }
+    }
+    let solution = state.solve(&Options::default());
+    assert!(solution.is_unsolvable());
+    assert_eq!(solution.reachable, Some(1));
+    assert!(solution.to_string().starts_with("No solution exists."));
+
+    let state = State::parse(INFO.example).unwrap();
+    let limits = Limits {
+        max_states: Some(5000),
+        ..Default::default()
+    };
+    let options = Options {
+        exhaustive: true,
+        ..Default::default()
+    };
+    let solution = state.solve_with_limits(&options, &limits);
+    assert!(solution.is_solved());
+    assert_eq!(solution.gave_up, Some(GaveUp::MaxStates));
+    assert_eq!(solution.reachable, None);
+    assert!(!solution.is_unsolvable());
+}
+
+#[test]
+fn test_deadlocks() {
+    use crate::Solution as _;
+    use crate::Solver as _;
+    let mut blocked = State::parse(INFO.example).unwrap();
+    for c in blocked.0.as_mut() {
+        let mut span = c.0.pop().unwrap();
+        c.1 = Some(span.bottom());
+        span.shrink_size(1);
+        if span.len() > 0 {
+            c.0.push(span);
+        }
+    }
+    assert_eq!(blocked.moves(&Rules::default()).next(), None);
+
+    // Every state found lost on the way to a solution really is lost.
+    let limits = Limits {
+        max_states: Some(20000),
+        ..Default::default()
+    };
+    let mut found = [0; Deadlock::ALL.len()];
+    for name in 1..=7 {
+        let path = format!("{}/data/cjul/{}.in", env!("CARGO_MANIFEST_DIR"), name);
+        let input = std::fs::read_to_string(path).unwrap();
+        for rules in [Rules::default(), "hard".parse().unwrap()] {
+            let state = State::parse_with_rules(&input, &rules).unwrap();
+            let mut searcher = Searcher {
+                rules: rules.clone(),
+                ..Default::default()
+            };
+            searcher.search(state, &limits);
+            for id in 0..searcher.states.len() {
+                let state = searcher.state(id as Id);
+                let deadlock = if state.is_success() {
+                    continue;
+                } else if state.moves(&rules).next().is_none() {
+                    Deadlock::Blocked
+                } else if state.sealed_pocket(&rules).is_some() {
+                    Deadlock::Sealed
+                } else {
+                    continue;
+                };
+                found[deadlock as usize] += 1;
+                let options = Options {
+                    rules: rules.clone(),
+                    exhaustive: true,
+                    ..Default::default()
+                };
+                assert!(state.solve_with_limits(&options, &limits).is_unsolvable());
+            }
+
+            let options = Options {
+                rules: rules.clone(),
+                ..Default::default()
+            };
+            let pruned = state.solve_with_limits(&options, &limits);
+            let kept = state.solve_with_limits(
+                &Options {
+                    keep_deadlocks: true,
+                    ..options
+                },
+                &limits,
+            );
+            assert_eq!(pruned.is_solved(), kept.is_solved());
+            assert!(pruned.stats.searched <= kept.stats.searched);
+        }
+    }
+    assert!(found.iter().all(|&n| n > 0));
+}
+
+#[test]
+fn test_rules() {
+    use crate::Solution as _;
+    use crate::Solver as _;
+    let rules: Rules = "hard,columns=6,ranks=6-8".parse().unwrap();
+    assert_eq!((rules.columns, rules.rows(), rules.run_len), (6, 2, 3));
+    assert!(!rules.slots);
+    assert!("columns=9".parse::<Rules>().is_err());
+    assert!("columns=5".parse::<Rules>().is_err());
+    assert!("ranks=6-T,run=10".parse::<Rules>().is_err());
+    assert!("ranks=K-6".parse::<Rules>().is_err());
+    let run = |spec: &str| spec.parse::<Rules>().map(|r| r.run_len);
+    assert_eq!(run("columns=8,run=5,ranks=6-K"), Ok(5));
+    assert_eq!(run("columns=8,ranks=6-K,run=5"), Ok(5));
+    assert_eq!(run("columns=8,ranks=6-K"), Ok(8));
+    assert!(run("columns=8,run=9,ranks=6-K").is_err());
+    assert!("easy".parse::<Rules>().is_err());
+
+    let deal = "7 8 8 7 7 6\n6 6 8 8 7 6";
+    let state = State::parse_with_rules(deal, &rules).unwrap();
+    let options = Options {
+        optimal: true,
+        rules: rules.clone(),
+        ..Default::default()
+    };
+    let solution = state.solve(&options);
+    assert!(solution.is_solved());
+    assert_eq!(
+        state.verify_with_rules(&solution.to_string(), &rules, Notation::Latin),
+        Ok(format!(
+            "Solved in {} steps.",
+            solution.moves.unwrap().len()
+        ))
+    );
+    let err = State::parse_with_rules(&deal.replace('6', "9"), &rules).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "line 1, column 11: card 9 is not in the deck"
+    );
+
+    // No free slots, and no move onto a card one rank higher.
+    let rules: Rules = "hard".parse().unwrap();
+    let state = State::parse_with_rules(INFO.example, &rules).unwrap();
+    let solution = state.solve(&Options {
+        rules,
+        ..Default::default()
+    });
+    assert!(solution.is_unsolvable());
+    assert_eq!(solution.reachable, Some(1));
+}
+
+#[test]
+fn test_heuristic() {
+    use crate::Solver as _;
+    let h: Heuristic = "tidy = 2 # comment\n\nstuck = 5, complete = 1"
+        .parse()
+        .unwrap();
+    assert_eq!((h.tidy, h.free_slot, h.stuck, h.complete), (2, 1, 5, 1));
+    assert_eq!(h.to_string().parse::<Heuristic>(), Ok(h.clone()));
+    assert!("tidy = 9".parse::<Heuristic>().is_err());
+    assert!("speed = 1".parse::<Heuristic>().is_err());
+
+    let rules = Rules::default();
+    let state = State::parse(INFO.example).unwrap();
+    let heavy = Heuristic {
+        tidy: 255,
+        ..Heuristic::default()
+    };
+    assert!(heavy.validate().is_err());
+    assert_eq!(state.score(&rules, &heavy), 255);
+    assert_eq!(
+        deal(3, &rules, Notation::Latin),
+        deal(3, &rules, Notation::Latin)
+    );
+    assert_ne!(
+        deal(3, &rules, Notation::Latin),
+        deal(4, &rules, Notation::Latin)
+    );
+    let state = State::parse_with_rules(&deal(3, &rules, Notation::Latin), &rules).unwrap();
+    let solution = state.solve(&Options {
+        heuristic: h,
+        ..Default::default()
+    });
+    if let Some(moves) = &solution.moves {
+        let summary = format!("Solved in {} steps.", moves.len());
+        assert_eq!(state.verify(&solution.to_string()), Ok(summary));
+    }
+}
+
+#[test]
+fn test_rate() {
+    let state = State::parse(INFO.example).unwrap();
+    let rating = state.rate(&Options::default(), &Limits::default());
+    assert_eq!((rating.steps, rating.proven_optimal), (Some(34), true));
+    assert_eq!(rating.first_moves, 30);
+    assert!((0.0..=100.0).contains(&rating.difficulty().unwrap()));
+    assert!(rating.to_string().starts_with("Difficulty: "));
+
+    let options = Options {
+        rules: "hard".parse().unwrap(),
+        ..Default::default()
+    };
+    let rating = state.rate(&options, &Limits::default());
+    assert!(rating.unsolvable);
+    assert_eq!(rating.difficulty(), None);
+    assert_eq!(
+        rating.to_string(),
+        "Unsolvable.\nSearched states: 1.\nLosing first moves: 0 of 0.\n"
+    );
+}
+
+#[test]
+fn test_rank_moves() {
+    let rules = Rules::default();
+    let position = "1: T K D V 10 9 8 7 6\n2: T K D V 10 9 8 7 6\n\
+                    3: T K D V 10 9 8 7 6\n4: T K D V 10 9 8 | 6\n6: 7";
+    let state = State::parse_with_rules(position, &rules).unwrap();
+    let ranking = state.rank_moves(&Options::default(), &Limits::default());
+    let summary: Vec<_> = ranking
+        .0
+        .iter()
+        .map(|r| (r.outlook.clone(), r.no_change, r.same_as))
+        .collect();
+    let wins = |n| Outlook::Wins {
+        moves_left: n,
+        proven: true,
+    };
+    assert_eq!(
+        summary,
+        [
+            (wins(1), false, None),
+            (wins(2), false, None),
+            (wins(2), true, None),
+        ]
+    );
+    assert_eq!(
+        ranking.to_string().lines().nth(1),
+        Some("   1  wins        1  Move [6] from 4 -> 6. [T K D V 10 9 8 6] to [7].")
+    );
+
+    // Two empty columns to move to.
+    let position = position.replace("8 | 6\n6: 7", "8 7 | 6");
+    let state = State::parse_with_rules(&position, &rules).unwrap();
+    let ranking = state.rank_moves(&Options::default(), &Limits::default());
+    let moves: Vec<_> = ranking.0.iter().map(|r| (r.m.to, r.same_as)).collect();
+    assert_eq!(moves, [(4, None), (5, Some(1))]);
+}
+
+#[test]
+fn test_limits() {
+    use crate::Solver as _;
+    let state = State::parse(INFO.example).unwrap();
+    let limits = Limits {
+        max_states: Some(100),
+        ..Default::default()
+    };
+    let solution = state.solve_with_limits(&Options::default(), &limits);
+    assert!(solution.moves.is_none());
+    assert_eq!(solution.gave_up, Some(GaveUp::MaxStates));
+    assert!(!solution.partial_moves.is_empty());
+}
+
+#[test]
+fn test_solve_data() {
+    use crate::Solution as _;
+    use crate::Solver as _;
+    for name in ["2", "7"] {
+        let path = format!("{}/data/cjul/{}.in", env!("CARGO_MANIFEST_DIR"), name);
+        let input = std::fs::read_to_string(path).unwrap();
+        let state = <State as crate::Solver>::parse(&input).unwrap();
+        let solution = state.solve(&Options::default());
+        assert!(solution.is_solved());
+        // Replay the moves.
+        let mut state = state;
+        for m in solution.moves.unwrap() {
+            let next = state
+                .next_states_by_moving(m.from, m.to, &Rules::default())
+                .into_iter()
+                .find(|(_, s)| s.card_count as usize == m.cards.len() && s.to_slot == m.to_slot);
+            state = next.unwrap().0;
+        }
+        assert!(state.is_success());
+    }
+}
+
+#[test]
+fn test_parent_cycle() {
+    use crate::Solver as _;
+    // Linking a state to a later parent with more steps used to let a
+    // shorter path close a cycle of parents, and explaining it never ended.
+    let deal = "\
+D T 8 10 8 K
+6 K 7 9 T 9
+D 6 D 7 D K
+10 8 T 9 8 V
+7 10 10 T V 6
+7 9 V 6 V K
+";
+    let state = State::parse(deal).unwrap();
+    let solution = state.solve(&Options::default());
+    let n = solution.moves.as_ref().unwrap().len();
+    assert_eq!(
+        state.verify(&solution.to_string()),
+        Ok(format!("Solved in {} steps.", n))
+    );
+}
+
+#[test]
+fn test_pack() {
+    let mut state = State::parse(INFO.example).unwrap();
+    for _ in 0..3 {
+        let mut packed = Vec::new();
+        state.pack(&mut packed);
+        assert!(packed.len() <= 6 + 36);
+        assert_eq!(State::unpack(&packed), state);
+        state = state.next_states(&Rules::default()).last().unwrap().0;
     }
 }
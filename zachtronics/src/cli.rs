@@ -0,0 +1,207 @@
+//! Minimal command line parsing.
+//!
+//! Flags are `--name` or `--name VALUE` (also `--name=VALUE`). Each flag
+//! can have a deprecated environment variable as fallback.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// A command line flag.
+pub struct Flag {
+    /// Long name, including the leading "--".
+    pub name: &'static str,
+    /// Optional short name, including the leading "-".
+    pub short: Option<&'static str>,
+    /// Name of the value. `None` for boolean flags.
+    pub value: Option<&'static str>,
+    /// Deprecated environment variable used if the flag is not given.
+    pub env: Option<&'static str>,
+    pub help: &'static str,
+}
+
+/// Flags accepted by all commands.
+pub const GLOBAL_FLAGS: &[Flag] = &[
+    Flag {
+        name: "--debug",
+        short: None,
+        value: None,
+        env: Some("D"),
+        help: "Print all debug messages. Same as --log trace",
+    },
+    Flag {
+        name: "--log",
+        short: None,
+        value: Some("SPEC"),
+        env: None,
+        help: "Log levels, like \"debug\" or \"dungeons=trace,cjul=info\"",
+    },
+    Flag {
+        name: "--log-file",
+        short: None,
+        value: Some("PATH"),
+        env: None,
+        help: "Write log messages to a file instead of stderr",
+    },
+    Flag {
+        name: "--verbose",
+        short: Some("-v"),
+        value: None,
+        env: Some("V"),
+        help: "Print more details",
+    },
+    Flag {
+        name: "--help",
+        short: Some("-h"),
+        value: None,
+        env: None,
+        help: "Print help",
+    },
+];
+
+/// Parsed flags and positional arguments.
+#[derive(Debug, Default)]
+pub struct Matches {
+    values: HashMap<&'static str, String>,
+    pub positional: Vec<String>,
+}
+
+impl Matches {
+    /// Whether a boolean flag is set.
+    pub fn flag(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    /// Value of a flag.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|s| s.as_str())
+    }
+
+    /// Parse the value of a flag.
+    pub fn parse_value<T: FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+        match self.value(name) {
+            None => Ok(None),
+            Some(v) => match v.parse::<T>() {
+                Ok(v) => Ok(Some(v)),
+                Err(_) => Err(format!("invalid value for {}: {:?}", name, v)),
+            },
+        }
+    }
+
+    /// Use deprecated environment variables for flags that are not given.
+    /// `skip_env` lists environment variables that are used for other purposes.
+    pub fn apply_env_fallback(&mut self, flag_groups: &[&[Flag]], skip_env: &[&str]) {
+        for flag in flag_groups.iter().flat_map(|g| g.iter()) {
+            let env_name = match flag.env {
+                Some(env_name) if !skip_env.contains(&env_name) => env_name,
+                _ => continue,
+            };
+            if self.values.contains_key(flag.name) {
+                continue;
+            }
+            if let Some(v) = env::var_os(env_name) {
+                eprintln!(
+                    "Warning: ${} is deprecated. Use {} instead.",
+                    env_name, flag.name
+                );
+                let v = v.to_string_lossy().into_owned();
+                self.values.insert(flag.name, v);
+            }
+        }
+    }
+}
+
+/// The first flag in `flag_groups` with the long or short `name`.
+pub fn find<'a>(flag_groups: &[&'a [Flag]], name: &str) -> Option<&'a Flag> {
+    flag_groups
+        .iter()
+        .flat_map(|g| g.iter())
+        .find(|f| f.name == name || f.short == Some(name))
+}
+
+/// Parse arguments. Flags not in `flag_groups` are errors.
+pub fn parse(args: &[String], flag_groups: &[&[Flag]]) -> Result<Matches, String> {
+    let mut matches = Matches::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            matches.positional.extend(iter.cloned());
+            break;
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            matches.positional.push(arg.clone());
+            continue;
+        }
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        let flag = find(flag_groups, name).ok_or_else(|| format!("unknown flag: {}", name))?;
+        let value = match (flag.value, inline_value) {
+            (None, None) => String::new(),
+            (None, Some(_)) => return Err(format!("{} does not take a value", flag.name)),
+            (Some(_), Some(v)) => v,
+            (Some(value_name), None) => match iter.next() {
+                Some(v) => v.clone(),
+                None => return Err(format!("{} requires {}", flag.name, value_name)),
+            },
+        };
+        matches.values.insert(flag.name, value);
+    }
+    Ok(matches)
+}
+
+/// Render help text for flags.
+pub fn flags_help(flag_groups: &[&[Flag]]) -> String {
+    let mut out = String::new();
+    let flags: Vec<&Flag> = flag_groups.iter().flat_map(|g| g.iter()).collect();
+    let usages: Vec<String> = flags
+        .iter()
+        .map(|f| {
+            let mut s = match f.short {
+                Some(short) => format!("{}, {}", short, f.name),
+                None => format!("    {}", f.name),
+            };
+            if let Some(value) = f.value {
+                write!(s, " <{}>", value).unwrap();
+            }
+            s
+        })
+        .collect();
+    let width = usages.iter().map(|s| s.len()).max().unwrap_or_default();
+    for (flag, usage) in flags.iter().zip(usages) {
+        write!(out, "  {:<width$}  {}", usage, flag.help).unwrap();
+        if let Some(env_name) = flag.env {
+            write!(out, " (deprecated: ${})", env_name).unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[test]
+fn test_parse() {
+    const FLAGS: &[Flag] = &[Flag {
+        name: "--quality",
+        short: Some("-q"),
+        value: Some("N"),
+        env: None,
+        help: "",
+    }];
+    let args = |s: &str| {
+        s.split_whitespace()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+    };
+    let m = parse(&args("a --quality 3 --debug b"), &[GLOBAL_FLAGS, FLAGS]).unwrap();
+    assert_eq!(m.positional, ["a", "b"]);
+    assert_eq!(m.parse_value::<u8>("--quality"), Ok(Some(3)));
+    assert!(m.flag("--debug"));
+    assert!(!m.flag("--verbose"));
+    let m = parse(&args("-q=4"), &[FLAGS]).unwrap();
+    assert_eq!(m.value("--quality"), Some("4"));
+    assert!(parse(&args("--quality"), &[FLAGS]).is_err());
+    assert!(parse(&args("--debug"), &[FLAGS]).is_err());
+    assert!(parse(&args("--debug=1"), &[GLOBAL_FLAGS]).is_err());
+}
@@ -1,36 +1,24 @@
+use crate::debug;
+use crate::json::Json;
+use crate::limits::Budget;
+use crate::limits::GaveUp;
+use crate::limits::Limits;
+use crate::or;
+use crate::trace;
+use crate::GameInfo;
+use crate::ParseError;
+use crate::Stats;
 use std::collections::HashMap;
+use std::fmt;
 use std::hash::Hash;
-use std::io::Read;
-use std::sync::atomic::AtomicBool;
-use std::sync::atomic::Ordering;
-
-static DEBUG: AtomicBool = AtomicBool::new(false);
-fn is_debug() -> bool {
-    DEBUG.load(Ordering::Acquire)
-}
-
-macro_rules! dprintln {
-    ($($t:tt)*) => {
-        if is_debug() {
-            eprintln!($($t)*);
-        }
-    }
-}
-macro_rules! or {
-    ($e:expr, $($s:tt)*) => {
-        match $e {
-            Some(v) => v,
-            None => $($s)*,
-        }
-    };
-}
+use std::mem::size_of;
 
 const COLUMNS: usize = 4;
 const ROWS: usize = 13;
 
 /// Problem to solve.
 #[derive(Debug)]
-struct Problem {
+pub struct Problem {
     cards: [[u8; COLUMNS]; ROWS],
 }
 
@@ -108,10 +96,10 @@ impl<'a> Iterator for CardStackSearch<'a> {
             frame.considered_column_len = column + 1;
             let mut next_card_lens = frame.card_lens;
             let score = frame.picked.score;
-            if DEBUG.load(Ordering::Acquire) {
-                let stack_str = self.card_stack_str();
-                eprintln!("  Considering pushing {card} to [{stack_str}]");
-            }
+            trace!(
+                "  Considering pushing {card} to [{}]",
+                self.card_stack_str()
+            );
             next_card_lens.0[column as usize] -= 1;
             let frame = CardStateSearchFrame {
                 card_lens: next_card_lens,
@@ -120,7 +108,7 @@ impl<'a> Iterator for CardStackSearch<'a> {
                     card,
                     column,
                     sum: next_sum,
-                    score: score, // Not correct. Will be fixed later.
+                    score, // Not correct. Will be fixed later.
                 },
             };
             // Fixup score.
@@ -160,19 +148,6 @@ impl CardStackPicked {
             score,
         }
     }
-
-    fn card_stack_str(&self) -> String {
-        card_stack_str(self.cards.into_iter().take(self.len as usize))
-    }
-
-    fn column_str(&self) -> String {
-        self.columns
-            .into_iter()
-            .take(self.len as usize)
-            .map(|v| (v + 1).to_string())
-            .collect::<Vec<String>>()
-            .join(" ")
-    }
 }
 
 impl<'a> CardStackSearch<'a> {
@@ -214,18 +189,18 @@ fn calculate_incremental_score_for_stack(stack: &[CardStateSearchFrame]) -> u8 {
     let mut score = 0u8;
     // +2: First card is a Jack.
     if stack.len() == 1 && picked.card == 11 {
-        dprintln!("  +2 (first Jack)");
+        trace!("  +2 (first Jack)");
         score += 2;
     }
     // +2: Exactly 15 or 31.
     if picked.sum == 15 || picked.sum == 31 {
-        dprintln!("  +2 (exactly 15 or 31)");
+        trace!("  +2 (exactly 15 or 31)");
         score += 2;
     }
     // +2, +6, +12: Set of same card. Overlaps are double counted.
     let same_card_count = (1..len)
         .rev()
-        .take_while(|&i| stack[i as usize].picked.card == stack[(i as usize) - 1].picked.card)
+        .take_while(|&i| stack[i].picked.card == stack[i - 1].picked.card)
         .count();
     if same_card_count > 0 {
         let same_card_score = match same_card_count {
@@ -234,18 +209,14 @@ fn calculate_incremental_score_for_stack(stack: &[CardStateSearchFrame]) -> u8 {
             _ => 12,
         };
         score += same_card_score;
-        dprintln!("  +{same_card_score} (same card #{same_card_count})");
+        trace!("  +{same_card_score} (same card #{same_card_count})");
     }
     // +3, +4, ..., +7: Run of 3 to 7 cards in any order.
     // Overlaps are double counted.
     for run_len in (3..=(7.min(len))).rev() {
         let end = len;
         let start = end - run_len;
-        if is_run_of_many_cards(
-            stack[start as usize..end as usize]
-                .iter()
-                .map(|f| f.picked.card),
-        ) {
+        if is_run_of_many_cards(stack[start..end].iter().map(|f| f.picked.card)) {
             let inc_score = run_len as u8;
             // Example in game: 5 4 2 A 3 5 4 2 A 3 is considered 5-run multiple times
             // and gets +5 x 6 in the game.
@@ -253,7 +224,7 @@ fn calculate_incremental_score_for_stack(stack: &[CardStateSearchFrame]) -> u8 {
             // counted as +3 and +6.
             // Example in game: 4 6 5 4 3 9, "6 5 4" and "6 5 4 3" are double
             // counted as +3 and + 4.
-            dprintln!("  +{inc_score} (run of #{run_len} cards)");
+            trace!("  +{inc_score} (run of #{run_len} cards)");
             score += inc_score;
             break;
         }
@@ -280,55 +251,64 @@ struct Solver<'a> {
 }
 
 impl Problem {
-    fn parse(s: &str) -> Option<Self> {
+    fn parse(s: &str) -> Result<Self, ParseError> {
         let mut cards = [[0u8; COLUMNS]; ROWS];
-        let mut card_counts = [0; 14];
-        for (i, line) in s.lines().take(ROWS).enumerate() {
-            let mut line_cards = [0u8; COLUMNS];
-            let mut pending_1 = false;
+        // Rows that each card appears in.
+        let mut card_rows: [Vec<usize>; 14] = Default::default();
+        let mut row = 0;
+        for (i, line) in s.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if row >= ROWS {
+                return Err(ParseError::at_line(i, format!("expected {} rows", ROWS)));
+            }
+            let chars: Vec<char> = line.chars().collect();
+            let mut count = 0;
             let mut j = 0;
-            for ch in line.chars() {
-                let v = match (pending_1, ch) {
-                    (false, '1') => {
-                        pending_1 = true;
-                        continue;
-                    }
-                    (true, _) => {
-                        pending_1 = false;
-                        let s: String = format!("1{}", ch);
-                        str_to_card_int(s.trim())
-                    }
-                    (false, ' ') => {
-                        continue;
-                    }
-                    (false, _) => {
-                        let s: String = ch.to_string();
-                        str_to_card_int(&s)
-                    }
-                }?;
-                card_counts[v as usize] += 1;
-                if card_counts[v as usize] > 4 {
-                    // Duplicated cards.
-                    dprintln!("Duplicated card: {s}");
-                    return None;
-                }
-                line_cards[j] = v;
+            while j < chars.len() {
+                let ch = chars[j];
+                let start = j;
                 j += 1;
-                if j >= 4 {
-                    break;
+                if ch.is_whitespace() {
+                    continue;
+                }
+                let card_str = if ch == '1' && chars.get(j) == Some(&'0') {
+                    j += 1;
+                    "10".to_string()
+                } else {
+                    ch.to_string()
+                };
+                let v = str_to_card_int(&card_str).ok_or_else(|| {
+                    ParseError::at(i, start, format!("unknown card {:?}", card_str))
+                })?;
+                if count >= COLUMNS {
+                    let msg = format!("row {} has more than {} cards", row + 1, COLUMNS);
+                    return Err(ParseError::at(i, start, msg));
                 }
+                card_rows[v as usize].push(row);
+                cards[row][count] = v;
+                count += 1;
             }
-            cards[i] = line_cards;
+            if count < COLUMNS {
+                let msg = format!("row {} has only {} cards", row + 1, count);
+                return Err(ParseError::at_line(i, msg));
+            }
+            row += 1;
+        }
+        if row < ROWS {
+            let msg = format!("expected {} rows, found {}", ROWS, row);
+            return Err(ParseError::new(msg));
         }
-        if card_counts.iter().fold(0, |a, v| a + v) != 52 {
-            dprintln!("Missing cards: {card_counts:?}");
-            return None;
+        for (v, rows) in card_rows.iter().enumerate() {
+            if rows.len() > 4 {
+                return Err(ParseError::too_many(card_int_to_str(v as u8), rows));
+            }
         }
-        // cards.reverse();
-        Some(Self { cards })
+        Ok(Self { cards })
     }
 
-    fn initial_state(&self) -> Solver {
+    fn initial_state(&self) -> Solver<'_> {
         let mut solver = Solver {
             problem: self,
             cache: HashMap::with_capacity(30559),
@@ -354,9 +334,7 @@ impl Problem {
 
 impl CardLens {
     fn initial_search_state() -> Self {
-        let mut state = Self::default();
-        state.0 = [ROWS as u8; COLUMNS];
-        state
+        Self([ROWS as u8; COLUMNS])
     }
 
     // End state: no more cards.
@@ -459,56 +437,69 @@ impl<'a> Solver<'a> {
         self.quality = quality;
     }
 
-    fn solve(&mut self) {
+    fn solve(&mut self, limits: &Limits) -> Solution {
         let mut state = CardLens::initial_search_state();
-        let total_score = self.best_score(&state);
-        println!(
-            "{}Score: {total_score}",
-            if self.quality == 10 { "Best " } else { "" }
-        );
+        let quality = self.quality;
+        let (total_score, gave_up) = match self.best_score(&state, &mut Budget::new(limits)) {
+            Ok(score) => (score, None),
+            Err(reason) => {
+                // Finish with a greedy search. Completed entries in the cache
+                // are still used, so this is at least as good as quality 0.
+                self.set_quality(0);
+                let score = self
+                    .best_score(&state, &mut Budget::new(&Limits::default()))
+                    .unwrap_or_default();
+                (score, Some(reason))
+            }
+        };
         // Trace back to figure out each step.
-        let mut step_count = 0;
-        loop {
-            let (_score, stack, next_state) = or!(self.cache.get(&state), break);
+        let mut steps = Vec::new();
+        while let Some((_score, stack, next_state)) = self.cache.get(&state) {
             if stack.len == 0 {
                 break;
             }
-            let next_score = self.cache.get(&next_state).map(|v| v.0).unwrap_or_default();
-            step_count += 1;
-            let stack_str = stack.card_stack_str();
-            let column_str = stack.column_str();
-            println!(
-                "{:>2}.{:>4} Take    [{}]\n        Columns [{}]",
-                step_count,
-                total_score - next_score,
-                stack_str,
-                column_str,
-            );
+            let next_score = self.cache.get(next_state).map(|v| v.0).unwrap_or_default();
+            let len = stack.len as usize;
+            steps.push(Step {
+                cards: stack.cards[..len].to_vec(),
+                columns: stack.columns[..len].to_vec(),
+                points: total_score - next_score,
+            });
             state = *next_state;
         }
-        // Stats
-        eprintln!(
-            "Searched states: {}. Cache hit: {}.",
-            self.cache.len(),
-            self.cache_hit_count,
-        );
-        if self.quality < 10 {
-            eprintln!(
-                "Search Quality ($Q): {}. May miss better solutions.",
-                self.quality
-            );
+        let stats = Stats {
+            searched: self.cache.len(),
+            cache_hits: self.cache_hit_count,
+            memory: self.memory_usage(),
+        };
+        Solution {
+            score: total_score,
+            steps,
+            quality,
+            gave_up,
+            stats,
         }
     }
 
-    fn best_score(&mut self, card_lens: &CardLens) -> Score {
+    /// Approximate memory used by the cache, in bytes.
+    fn memory_usage(&self) -> usize {
+        self.cache.capacity() * (size_of::<(CardLens, (Score, CardStackPicked, CardLens))>() + 1)
+    }
+
+    fn best_score(&mut self, card_lens: &CardLens, budget: &mut Budget) -> Result<Score, GaveUp> {
         if card_lens.is_end_state() {
-            return 0;
+            return Ok(0);
         }
         if let Some(v) = self.cache.get(card_lens) {
             self.cache_hit_count += 1;
-            return v.0;
+            return Ok(v.0);
         }
-        dprintln!("Calculating best score for {card_lens:?}");
+        budget.check(self.cache.len(), self.memory_usage())?;
+        budget.report(self.cache.len(), |p| {
+            let left: usize = card_lens.0.iter().map(|&v| v as usize).sum();
+            p.depth = Some(ROWS * COLUMNS - left);
+        });
+        debug!("Calculating best score for {card_lens:?}");
         let mut best = (0, CardStackPicked::default(), CardLens::default());
         let stack_search = CardStackSearch::from_problem_card_lens(self.problem, *card_lens);
         let mut choices: Vec<_> = stack_search.collect();
@@ -530,16 +521,16 @@ impl<'a> Solver<'a> {
                         break;
                     }
                 }
-                let next_score = self.best_score(&next_card_lens);
+                let next_score = self.best_score(&next_card_lens, budget)?;
                 let score = next_score + stack.score;
                 if score > best.0 || best.0 == 0 {
                     best = (score, stack, next_card_lens);
-                    dprintln!("  Update best to {score} ({stack:?} {next_card_lens:?})");
+                    debug!("  Update best to {score} ({stack:?} {next_card_lens:?})");
                 }
             }
         }
         self.cache.insert(*card_lens, best);
-        best.0
+        Ok(best.0)
     }
 }
 
@@ -569,18 +560,369 @@ fn card_int_to_str(v: u8) -> &'static str {
     ][v as usize]
 }
 
-pub(crate) fn main() {
-    DEBUG.store(std::env::var_os("D").is_some(), Ordering::Release);
-    let s = {
-        let mut s = String::new();
-        let mut input = std::io::stdin();
-        input.read_to_string(&mut s).unwrap();
-        s
-    };
-    let p = Problem::parse(&s).unwrap();
-    let mut s = p.initial_state();
-    if let Some(quality) = std::env::var("Q").ok().and_then(|v| v.parse::<u8>().ok()) {
-        s.set_quality(quality);
+pub const INFO: GameInfo = GameInfo {
+    name: "cribbage",
+    aliases: &["cribbage-solitaire"],
+    description: "Cribbage Solitaire from the Zachtronics Solitaire Collection",
+    format: "\
+13 lines of 4 cards. Each line is a row. The last line is the row
+that is available first.
+Cards are A, 2-10, J, Q, K (case insensitive). Spaces are optional.",
+    example: include_str!("../data/cribbage/a.in"),
+};
+
+/// Search options.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Search quality (0 to 10). 10 finds the best score.
+    pub quality: u8,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            quality: if cfg!(debug_assertions) { 3 } else { 10 },
+        }
     }
-    s.solve();
+}
+
+/// A "take cards" step in a solution.
+#[derive(Clone, Debug)]
+pub struct Step {
+    /// Taken cards. 1 is Ace, 13 is King.
+    pub cards: Vec<u8>,
+    /// Columns the cards are taken from. 0-based.
+    pub columns: Vec<u8>,
+    /// Total points after this step.
+    pub points: u8,
+}
+
+/// Result of [`Problem::solve`](crate::Solver::solve).
+#[derive(Clone, Debug)]
+pub struct Solution {
+    pub score: u8,
+    pub steps: Vec<Step>,
+    /// Quality used by the search. Lower than 10 may miss better solutions.
+    pub quality: u8,
+    /// Set if the search stopped early. The steps are then from a greedy search.
+    pub gave_up: Option<GaveUp>,
+    pub stats: Stats,
+}
+
+impl fmt::Display for Solution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}Score: {}",
+            if self.quality == 10 && self.gave_up.is_none() {
+                "Best "
+            } else {
+                ""
+            },
+            self.score
+        )?;
+        for (i, step) in self.steps.iter().enumerate() {
+            let column_str = step
+                .columns
+                .iter()
+                .map(|v| (v + 1).to_string())
+                .collect::<Vec<String>>()
+                .join(" ");
+            writeln!(
+                f,
+                "{:>2}.{:>4} Take    [{}]\n        Columns [{}]",
+                i + 1,
+                step.points,
+                card_stack_str(step.cards.iter().copied()),
+                column_str,
+            )?;
+        }
+        if let Some(reason) = self.gave_up {
+            writeln!(f, "Gave up ({}). May miss better solutions.", reason)?;
+        } else if self.quality < 10 {
+            writeln!(
+                f,
+                "Search Quality: {}. May miss better solutions.",
+                self.quality
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl crate::Solution for Solution {
+    fn is_solved(&self) -> bool {
+        true
+    }
+
+    fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    fn score(&self) -> Option<usize> {
+        Some(self.score as usize)
+    }
+
+    fn step_count(&self) -> Option<usize> {
+        Some(self.steps.len())
+    }
+
+    fn gave_up(&self) -> Option<GaveUp> {
+        self.gave_up
+    }
+
+    fn to_json(&self) -> Json {
+        let mut total = 0;
+        let steps = self.steps.iter().map(|step| {
+            let cards: Vec<&str> = step.cards.iter().map(|&c| card_int_to_str(c)).collect();
+            let columns: Vec<u8> = step.columns.iter().map(|c| c + 1).collect();
+            let points = step.points - total;
+            total = step.points;
+            Json::object([
+                ("cards", Json::from(cards)),
+                ("columns", columns.into()),
+                ("points", points.into()),
+                ("total", total.into()),
+            ])
+        });
+        Json::object([
+            ("score", Json::from(self.score)),
+            ("quality", self.quality.into()),
+            ("steps", Json::Array(steps.collect())),
+        ])
+    }
+}
+
+impl crate::Solver for Problem {
+    type Options = Options;
+    type Solution = Solution;
+
+    fn parse(input: &str) -> Result<Self, ParseError> {
+        Problem::parse(input)
+    }
+
+    fn solve_with_limits(&self, options: &Options, limits: &Limits) -> Solution {
+        let mut s = self.initial_state();
+        s.set_quality(options.quality);
+        s.solve(limits)
+    }
+
+    fn verify(&self, solution: &str) -> Result<String, String> {
+        let mut card_lens = CardLens::initial_search_state();
+        let mut total = 0;
+        let mut step_count = 0;
+        let mut declared_score = None;
+        // Cards and declared total score of a "Take" line, waiting for "Columns".
+        let mut pending_take: Option<(Vec<u8>, Option<usize>)> = None;
+        for line in solution.lines() {
+            if let Some((_, rest)) = line.split_once("Score:") {
+                let score = rest.trim().parse::<usize>();
+                declared_score = Some(score.map_err(|_| format!("bad score: {}", line))?);
+            } else if let Some((left, rest)) = line.split_once("Take") {
+                let cards = parse_bracket(rest, str_to_card_int)
+                    .ok_or_else(|| format!("cannot parse cards: {}", line))?;
+                let declared_total = left.split_whitespace().last().and_then(|s| s.parse().ok());
+                pending_take = Some((cards, declared_total));
+            } else if let Some((_, rest)) = line.split_once("Columns") {
+                let columns = parse_bracket(rest, |s| s.parse::<u8>().ok())
+                    .ok_or_else(|| format!("cannot parse columns: {}", line))?;
+                let (cards, declared_total) = or!(
+                    pending_take.take(),
+                    return Err(format!("no cards before: {}", line))
+                );
+                step_count += 1;
+                let err = |msg: String| format!("Step {}: {}", step_count, msg);
+                total += self
+                    .replay_step(&mut card_lens, &cards, &columns)
+                    .map_err(err)?;
+                if let Some(declared_total) = declared_total {
+                    if declared_total != total {
+                        let msg = format!("declared total {} but actual {}", declared_total, total);
+                        return Err(err(msg));
+                    }
+                }
+            }
+        }
+        if step_count == 0 {
+            return Err("no \"Take [..]\" and \"Columns [..]\" lines found".to_string());
+        }
+        if !card_lens.is_end_state() {
+            let left: u8 = card_lens.0.iter().sum();
+            return Err(format!(
+                "{} cards are left after {} steps",
+                left, step_count
+            ));
+        }
+        if let Some(declared_score) = declared_score {
+            if declared_score != total {
+                return Err(format!(
+                    "declared score {} but actual {}",
+                    declared_score, total
+                ));
+            }
+        }
+        Ok(format!("Score: {} in {} steps.", total, step_count))
+    }
+}
+
+impl Problem {
+    /// Take cards from columns (1-based) as a card stack. Return the score.
+    fn replay_step(
+        &self,
+        card_lens: &mut CardLens,
+        cards: &[u8],
+        columns: &[u8],
+    ) -> Result<usize, String> {
+        if cards.len() != columns.len() || cards.is_empty() {
+            return Err(format!(
+                "{} cards but {} columns",
+                cards.len(),
+                columns.len()
+            ));
+        }
+        let mut stack = Vec::with_capacity(cards.len());
+        let mut sum = 0;
+        let mut score = 0;
+        for (&card, &column) in cards.iter().zip(columns) {
+            let card_str = card_int_to_str(card);
+            if column == 0 || column as usize > COLUMNS {
+                return Err(format!("invalid column {}", column));
+            }
+            let column = column - 1;
+            match self.card_at(*card_lens, column) {
+                None => return Err(format!("column {} is empty", column + 1)),
+                Some(c) if c != card => {
+                    return Err(format!(
+                        "column {} has {} instead of {}",
+                        column + 1,
+                        card_int_to_str(c),
+                        card_str
+                    ))
+                }
+                Some(_) => {}
+            }
+            sum += card.min(10);
+            if sum > 31 {
+                return Err(format!("stack sum {} exceeds 31 after {}", sum, card_str));
+            }
+            card_lens.0[column as usize] -= 1;
+            stack.push(CardStateSearchFrame {
+                card_lens: *card_lens,
+                considered_column_len: 0,
+                picked: CardPicked {
+                    card,
+                    column,
+                    sum,
+                    score: 0,
+                },
+            });
+            score += calculate_incremental_score_for_stack(&stack) as usize;
+        }
+        for column in 0..(COLUMNS as u8) {
+            if let Some(card) = self.card_at(*card_lens, column) {
+                if sum + card.min(10) <= 31 {
+                    return Err(format!(
+                        "stack ended at sum {} but {} in column {} still fits",
+                        sum,
+                        card_int_to_str(card),
+                        column + 1
+                    ));
+                }
+            }
+        }
+        Ok(score)
+    }
+}
+
+/// Parse "[a b c]".
+fn parse_bracket<T>(s: &str, parse: impl Fn(&str) -> Option<T>) -> Option<Vec<T>> {
+    let s = s.trim().strip_prefix('[')?.strip_suffix(']')?;
+    s.split_whitespace().map(parse).collect()
+}
+
+#[test]
+fn test_parse_errors() {
+    let err = |from: &str, to: &str| {
+        let input = INFO.example.replacen(from, to, 1);
+        Problem::parse(&input).unwrap_err().to_string()
+    };
+    assert_eq!(
+        err("A  7  9", "A  7  X"),
+        "line 2, column 7: unknown card \"X\""
+    );
+    assert_eq!(
+        err("A  7  9  Q", "A  7  9"),
+        "line 2: row 2 has only 3 cards"
+    );
+    assert_eq!(
+        err("A  7  9  Q", "A  7  9  Q Q"),
+        "line 2, column 12: row 2 has more than 4 cards"
+    );
+    assert_eq!(
+        err("A  7  9  Q", "A  7  9  K"),
+        "rank K appears 5 times (rows 1, 2, 5, 9, 10)"
+    );
+    assert_eq!(err("6  2  6  8\n", ""), "expected 13 rows, found 12");
+}
+
+#[test]
+fn test_verify() {
+    use crate::Solver as _;
+    let problem = Problem::parse(INFO.example).unwrap();
+    let solution = problem.solve(&Options { quality: 3 }).to_string();
+    let verify = |s: &str| problem.verify(s);
+    assert_eq!(verify(&solution), Ok("Score: 79 in 12 steps.".to_string()));
+
+    let bad = solution.replacen("Score: 79", "Score: 80", 1);
+    assert_eq!(verify(&bad).unwrap_err(), "declared score 80 but actual 79");
+    let bad = solution.replacen("[4 6 6 6 3]", "[6 4 6 6 3]", 1);
+    assert_eq!(
+        verify(&bad).unwrap_err(),
+        "Step 1: column 1 has 4 instead of 6"
+    );
+    let bad = solution.replacen("   8 Take", "   9 Take", 1);
+    assert_eq!(
+        verify(&bad).unwrap_err(),
+        "Step 1: declared total 9 but actual 8"
+    );
+    let lines: Vec<&str> = solution.lines().collect();
+    assert_eq!(
+        verify(&lines[..5].join("\n")).unwrap_err(),
+        "43 cards are left after 2 steps"
+    );
+}
+
+#[test]
+fn test_solve_data() {
+    use crate::Solver as _;
+    let path = format!("{}/data/cribbage/a.in", env!("CARGO_MANIFEST_DIR"));
+    let input = std::fs::read_to_string(path).unwrap();
+    let problem = <Problem as crate::Solver>::parse(&input).unwrap();
+    let solution = problem.solve(&Options { quality: 3 });
+    assert_eq!(solution.score, 79);
+    assert_eq!(solution.steps.len(), 12);
+    let cards: usize = solution.steps.iter().map(|s| s.cards.len()).sum();
+    assert_eq!(cards, 52);
+    // The search finished, so it is not repeated.
+    assert_eq!(solution.gave_up, None);
+    assert_eq!(
+        (solution.stats.searched, solution.stats.cache_hits),
+        (699, 3515)
+    );
+}
+
+#[test]
+fn test_limits() {
+    use crate::Solver as _;
+    let problem = Problem::parse(INFO.example).unwrap();
+    let limits = Limits {
+        max_states: Some(10),
+        ..Default::default()
+    };
+    let solution = problem.solve_with_limits(&Options { quality: 10 }, &limits);
+    assert_eq!(solution.gave_up, Some(GaveUp::MaxStates));
+    let cards: usize = solution.steps.iter().map(|s| s.cards.len()).sum();
+    assert_eq!(cards, 52);
+    let text = solution.to_string();
+    assert!(problem.verify(&text).is_ok(), "{}", text);
 }
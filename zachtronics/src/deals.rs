@@ -0,0 +1,237 @@
+//! Commands on cjul deals: random deals, statistics of solving many of
+//! them, the difficulty of a deal, the outlook of each move from a
+//! position, and the endgame tablebase.
+
+use std::fs;
+
+use zachtronics::cjul;
+
+use crate::batch;
+use crate::cli::Flag;
+use crate::cli::Matches;
+use crate::registry;
+use crate::registry::Game;
+
+pub const DEAL_FLAGS: &[Flag] = &[
+    Flag {
+        name: "--seed",
+        short: None,
+        value: Some("N"),
+        env: None,
+        help: "Seed of the deal. Default: 1",
+    },
+    Flag {
+        name: "--rules",
+        short: None,
+        value: Some("SPEC"),
+        env: None,
+        help: "Rule variant of the deals",
+    },
+    Flag {
+        name: "--notation",
+        short: None,
+        value: Some("latin|cyrillic|english"),
+        env: None,
+        help: "Names of the face cards: V D K T, В Д К Т or J Q K A",
+    },
+];
+
+pub const STATS_FLAGS: &[Flag] = &[
+    Flag {
+        name: "--deals",
+        short: Some("-n"),
+        value: Some("N"),
+        env: None,
+        help: "Number of deals, with consecutive seeds. Default: 100",
+    },
+    Flag {
+        name: "--seed",
+        short: None,
+        value: Some("N"),
+        env: None,
+        help: "Seed of the first deal. Default: 1",
+    },
+    batch::JOBS_FLAG,
+];
+
+pub const TABLEBASE_FLAGS: &[Flag] = &[
+    Flag {
+        name: "--loose",
+        short: None,
+        value: Some("N"),
+        env: None,
+        help: "Most cards outside the complete columns and the anchored run. Default: 7",
+    },
+    Flag {
+        name: "--rules",
+        short: None,
+        value: Some("SPEC"),
+        env: None,
+        help: "Rule variant of the tablebase",
+    },
+    Flag {
+        name: "--output",
+        short: Some("-o"),
+        value: Some("FILE"),
+        env: None,
+        help: "Write the tablebase to FILE",
+    },
+];
+
+/// States searched per deal unless --max-states is given. High enough that
+/// most deals finish, so the shares reflect the deals more than the cap.
+const DEFAULT_MAX_STATES: usize = 1_000_000;
+
+/// Run the deal command: print a random deal.
+pub fn deal(m: &Matches) -> Result<(), String> {
+    if let Some(arg) = m.positional.first() {
+        return Err(format!("unexpected argument: {}", arg));
+    }
+    let seed = m.parse_value("--seed")?.unwrap_or(1);
+    let notation = registry::cjul_notation(m)?;
+    print!("{}", cjul::deal(seed, &registry::cjul_rules(m)?, notation));
+    Ok(())
+}
+
+/// Run the rate command: rate the difficulty of the deal on stdin.
+pub fn rate(m: &Matches, input: &str) -> Result<(), String> {
+    if let Some(arg) = m.positional.first() {
+        return Err(format!("unexpected argument: {}", arg));
+    }
+    let options = registry::cjul_options(m)?;
+    let state = cjul::State::parse_with_rules(input, &options.rules)
+        .map_err(|e| format!("invalid puzzle: {}", e))?;
+    let limits = registry::limits(m)?;
+    print!("{}", state.rate(&options, &limits));
+    Ok(())
+}
+
+/// Run the moves command: rank all moves from the position on stdin.
+pub fn moves(m: &Matches, input: &str) -> Result<(), String> {
+    if let Some(arg) = m.positional.first() {
+        return Err(format!("unexpected argument: {}", arg));
+    }
+    let options = registry::cjul_options(m)?;
+    let state = cjul::State::parse_with_rules(input, &options.rules)
+        .map_err(|e| format!("invalid puzzle: {}", e))?;
+    let limits = registry::limits(m)?;
+    print!("{}", state.rank_moves(&options, &limits));
+    Ok(())
+}
+
+/// Run the tablebase command: build an endgame tablebase and save it.
+pub fn tablebase(m: &Matches) -> Result<(), String> {
+    if let Some(arg) = m.positional.first() {
+        return Err(format!("unexpected argument: {}", arg));
+    }
+    let path = m.value("--output").ok_or("missing --output")?;
+    let loose = m.parse_value("--loose")?.unwrap_or(7);
+    let tablebase = cjul::Tablebase::build(&registry::cjul_rules(m)?, loose)?;
+    fs::write(path, tablebase.to_bytes()).map_err(|e| format!("{}: {}", path, e))?;
+    eprintln!(
+        "{} winning positions, up to {} moves.",
+        tablebase.len(),
+        tablebase.longest()
+    );
+    Ok(())
+}
+
+/// Outcome of solving a deal.
+struct Outcome {
+    solved: bool,
+    unsolvable: bool,
+    searched: usize,
+    step_count: Option<usize>,
+}
+
+/// Run the stats command. `m` also has the cjul solve flags.
+pub fn stats(game: &Game, m: &Matches) -> Result<(), String> {
+    if let Some(arg) = m.positional.first() {
+        return Err(format!("unexpected argument: {}", arg));
+    }
+    let count: usize = m.parse_value("--deals")?.unwrap_or(100);
+    let seed: u64 = m.parse_value("--seed")?.unwrap_or(1);
+    let jobs = batch::jobs(m)?;
+    if count == 0 {
+        return Err("no deals".to_string());
+    }
+    let rules = registry::cjul_rules(m)?;
+    let mut limits = registry::limits(m)?;
+    limits.max_states = limits.max_states.or(Some(DEFAULT_MAX_STATES));
+
+    let outcomes = batch::run_parallel(jobs, count, |i| {
+        let input = cjul::deal(seed + i as u64, &rules, cjul::Notation::Latin);
+        (game.solve)(&input, m, &limits).map(|solution| Outcome {
+            solved: solution.is_solved(),
+            unsolvable: solution.is_unsolvable(),
+            searched: solution.stats().searched,
+            step_count: solution.step_count(),
+        })
+    });
+    let outcomes = outcomes.into_iter().collect::<Result<Vec<_>, _>>()?;
+    print!("{}", render(&outcomes, seed));
+    Ok(())
+}
+
+fn render(outcomes: &[Outcome], seed: u64) -> String {
+    let n = outcomes.len();
+    let solved = outcomes.iter().filter(|o| o.solved).count();
+    let unsolvable = outcomes.iter().filter(|o| o.unsolvable).count();
+    let gave_up = n - solved - unsolvable;
+    let share = |k: usize| format!("{} ({:.1}%)", k, k as f64 * 100.0 / n as f64);
+    let mut out = format!(
+        "Deals: {} (seeds {} to {}).\n",
+        n,
+        seed,
+        seed + n as u64 - 1
+    );
+    out += &format!(
+        "Solved: {}. Unsolvable: {}. Gave up: {}.\n",
+        share(solved),
+        share(unsolvable),
+        share(gave_up)
+    );
+    let searched: Vec<usize> = outcomes.iter().map(|o| o.searched).collect();
+    out += &format!("Searched states: {}.\n", summary(searched));
+    let steps: Vec<usize> = outcomes.iter().filter_map(|o| o.step_count).collect();
+    if !steps.is_empty() {
+        out += &format!("Solution steps: {}.\n", summary(steps));
+    }
+    out
+}
+
+/// Percentiles and mean of a non-empty list.
+fn summary(mut values: Vec<usize>) -> String {
+    values.sort_unstable();
+    let mean = values.iter().sum::<usize>() as f64 / values.len() as f64;
+    let parts: Vec<String> = [
+        ("min", 0),
+        ("p10", 10),
+        ("p50", 50),
+        ("p90", 90),
+        ("max", 100),
+    ]
+    .iter()
+    .map(|&(name, p)| format!("{} {}", name, percentile(&values, p)))
+    .collect();
+    format!("{}, mean {:.1}", parts.join(", "), mean)
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[usize], p: usize) -> usize {
+    let rank = (p * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+#[test]
+fn test_percentile() {
+    let values: Vec<usize> = (1..=10).collect();
+    assert_eq!(percentile(&values, 0), 1);
+    assert_eq!(percentile(&values, 50), 5);
+    assert_eq!(percentile(&values, 90), 9);
+    assert_eq!(percentile(&values, 100), 10);
+    assert_eq!(
+        summary(vec![3, 1, 2]),
+        "min 1, p10 1, p50 2, p90 3, max 3, mean 2.0"
+    );
+}
@@ -1,51 +1,42 @@
-#![allow(dead_code)]
-
-use std::io;
-use std::io::Read;
+use crate::debug;
+use crate::json::Json;
+use crate::limits::Budget;
+use crate::limits::GaveUp;
+use crate::limits::Limits;
+use crate::or;
+use crate::trace;
+use crate::GameInfo;
+use crate::ParseError;
+use crate::Stats;
+use std::fmt;
+use std::mem::size_of;
 use std::ops::ControlFlow;
 
-static mut DEBUG: bool = false;
-macro_rules! dprintln {
-    ($($t:tt)*) => {
-        if unsafe { DEBUG } {
-            eprintln!($($t)*);
-        }
-    }
-}
-macro_rules! or {
-    ($e:expr, $($s:tt)*) => {
-        match $e {
-            Some(v) => v,
-            None => $($s)*,
-        }
-    };
-}
-
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 #[repr(u8)]
 enum GoalCell {
+    #[default]
     Empty,
     Monster,
     Chest,
 }
 
-impl Default for GoalCell {
-    fn default() -> Self {
-        Self::Empty
-    }
-}
-
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 #[repr(u8)]
 enum StateCell {
+    #[default]
     Undecided,
     Empty,
     Wall,
 }
 
-impl Default for StateCell {
-    fn default() -> Self {
-        Self::Undecided
+impl StateCell {
+    fn to_char(self) -> char {
+        match self {
+            StateCell::Undecided => '?',
+            StateCell::Empty => '.',
+            StateCell::Wall => '#',
+        }
     }
 }
 
@@ -84,6 +75,12 @@ struct Pos {
     x: u8,
 }
 
+impl fmt::Display for Pos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "row {} column {}", self.y + 1, self.x + 1)
+    }
+}
+
 impl Pos {
     fn abs_diff(self, rhs: Pos) -> u8 {
         self.x.abs_diff(rhs.x) + self.y.abs_diff(rhs.y)
@@ -119,18 +116,20 @@ impl Pos {
     }
 }
 
+/// The puzzle: wall counts of each row and column, monsters and chests.
 #[derive(Debug)]
-struct Goal {
+pub struct Goal {
     grid: [[GoalCell; NS]; NS],
     sum_columns: [u8; NS],
     sum_rows: [u8; NS],
     // Derived
     monster_positions: Vec<Pos>,
     chest_positions: Vec<Pos>,
-    // Config
-    multi_solution: bool,
 }
 
+/// Walls of a solved dungeon. `true` means a wall.
+pub type Grid = [[bool; NS]; NS];
+
 #[derive(Clone, Debug)]
 struct State<'a> {
     goal: &'a Goal,
@@ -139,81 +138,103 @@ struct State<'a> {
     grid: [[StateCell; NS]; NS],
     last_chest_okay_positions: Vec<Option<Pos>>,
 
-    solution_count: usize,
+    solutions: Vec<Grid>,
     search_count: usize,
+    // Set if a limit is reached.
+    gave_up: Option<GaveUp>,
+    // Furthest position reached, and the grid at that time.
+    deepest: Option<(Pos, [[StateCell; NS]; NS])>,
+
+    // Config
+    multi_solution: bool,
 }
 
 fn char_to_u8(ch: char) -> u8 {
     let v = ch as u8;
-    if v >= b'0' && v <= b'9' {
-        let v = v - b'0';
-        return v;
+    if v.is_ascii_digit() {
+        return v - b'0';
     }
-    return 0;
+    0
 }
 
 impl Goal {
-    fn read_from_ascii(input: &mut dyn Read) -> io::Result<Self> {
-        let mut s = String::new();
+    fn parse(s: &str) -> Result<Self, ParseError> {
         let mut sum_columns = [0u8; NS];
         let mut sum_rows = [0u8; NS];
         let mut grid = [[GoalCell::Empty; NS]; NS];
         let mut monster_positions = Vec::new();
         let mut chest_positions = Vec::new();
-        input.read_to_string(&mut s)?;
-        for (i, line) in s
-            .lines()
-            .filter(|l| !l.trim().is_empty())
-            .enumerate()
-            .take(NS + 1)
-        {
-            if i == 0 {
-                for (x, ch) in line.trim().chars().enumerate().take(NS) {
-                    sum_columns[x] = char_to_u8(ch);
-                }
-            } else {
-                let y = i - 1;
-                sum_rows[y] = char_to_u8(line.chars().next().unwrap_or('0'));
-                let row = &line[1..];
-                for (x, ch) in row.chars().enumerate().take(NS) {
-                    grid[y][x] = match ch {
-                        'M' => {
-                            monster_positions.push(Pos {
-                                y: y as _,
-                                x: x as _,
-                            });
-                            GoalCell::Monster
-                        }
-                        'C' => {
-                            chest_positions.push(Pos {
-                                y: y as _,
-                                x: x as _,
-                            });
-                            GoalCell::Chest
-                        }
-                        _ => GoalCell::Empty,
+        let digit = |i: usize, j: usize, ch: char| {
+            ch.to_digit(10)
+                .map(|v| v as u8)
+                .filter(|&v| v <= N)
+                .ok_or_else(|| ParseError::at(i, j, format!("expected a wall count, got {:?}", ch)))
+        };
+        let mut lines = s.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+
+        let (i, line) = lines.next().ok_or_else(|| ParseError::new("empty input"))?;
+        let indent = line.chars().take_while(|c| c.is_whitespace()).count();
+        let chars: Vec<char> = line.trim().chars().collect();
+        if chars.len() != NS {
+            let msg = format!("expected {} column wall counts, found {}", NS, chars.len());
+            return Err(ParseError::at_line(i, msg));
+        }
+        for (x, &ch) in chars.iter().enumerate() {
+            sum_columns[x] = digit(i, indent + x, ch)?;
+        }
+
+        for y in 0..NS {
+            let (i, line) = lines
+                .next()
+                .ok_or_else(|| ParseError::new(format!("expected {} rows, found {}", NS, y)))?;
+            let chars: Vec<char> = line.trim_end().chars().collect();
+            sum_rows[y] = digit(i, 0, chars[0])?;
+            let cells = &chars[1..];
+            if cells.len() != NS {
+                let msg = format!("row {} has {} cells, expected {}", y + 1, cells.len(), NS);
+                return Err(ParseError::at_line(i, msg));
+            }
+            for (x, &ch) in cells.iter().enumerate() {
+                let pos = Pos {
+                    y: y as _,
+                    x: x as _,
+                };
+                grid[y][x] = match ch {
+                    'M' => {
+                        monster_positions.push(pos);
+                        GoalCell::Monster
+                    }
+                    'C' => {
+                        chest_positions.push(pos);
+                        GoalCell::Chest
+                    }
+                    '.' => GoalCell::Empty,
+                    _ => {
+                        let msg = format!("unknown cell {:?}", ch);
+                        return Err(ParseError::at(i, x + 1, msg));
                     }
                 }
             }
         }
-        let multi_solution = std::env::var_os("M").is_some();
-        let v = Self {
+        if let Some((i, _)) = lines.next() {
+            return Err(ParseError::at_line(i, format!("expected {} rows", NS)));
+        }
+        Ok(Self {
             grid,
             sum_columns,
             sum_rows,
             monster_positions,
             chest_positions,
-            multi_solution,
-        };
-        Ok(v)
+        })
     }
+
     fn get(&self, p: Pos) -> GoalCell {
         self.grid[p.y as usize][p.x as usize]
     }
 }
 
 impl<'a> State<'a> {
-    fn from_goal(goal: &'a Goal) -> Self {
+    fn from_goal(goal: &'a Goal, multi_solution: bool) -> Self {
         Self {
             goal,
             sum_columns: Default::default(),
@@ -222,29 +243,41 @@ impl<'a> State<'a> {
 
             last_chest_okay_positions: vec![None; goal.chest_positions.len()],
 
-            solution_count: 0,
+            solutions: Vec::new(),
             search_count: 0,
+            gave_up: None,
+            deepest: None,
+
+            multi_solution,
         }
     }
 
-    fn print_grid(&self, pos: Pos) {
+    /// Decided cells up to `pos`.
+    fn grid_str(&self, pos: Pos) -> String {
+        let mut out = String::new();
         for y in 0..=pos.y {
             let n = if y == pos.y { pos.x + 1 } else { N };
-            for x in 0..n {
-                let c = match self.get(Pos { x, y }) {
-                    StateCell::Undecided => '?',
-                    StateCell::Empty => '.',
-                    StateCell::Wall => '#',
-                };
-                eprint!("{}", c);
-            }
-            eprintln!();
+            out.push('\n');
+            out.extend((0..n).map(|x| self.get(Pos { x, y }).to_char()));
         }
+        out
     }
 
-    fn search(&mut self, pos: Pos) -> ControlFlow<(), ()> {
-        dprintln!("Search {pos:?}");
+    fn search(&mut self, pos: Pos, budget: &mut Budget) -> ControlFlow<(), ()> {
+        trace!("Search {pos:?}");
         self.search_count += 1;
+        let memory = self.solutions.capacity() * size_of::<Grid>();
+        if let Err(reason) = budget.check(self.search_count, memory) {
+            self.gave_up = Some(reason);
+            return ControlFlow::Break(());
+        }
+        if self.deepest.is_none_or(|(p, _)| pos > p) {
+            self.deepest = Some((pos, self.grid));
+        }
+        budget.report(self.search_count, |p| {
+            p.depth = Some(pos.y as usize * NS + pos.x as usize);
+            p.best_score = self.deepest.map(|(d, _)| d.y as usize * NS + d.x as usize);
+        });
 
         let orig_state = self.get(pos);
         let orig_sum_row = self.sum_rows[pos.y as usize];
@@ -261,9 +294,7 @@ impl<'a> State<'a> {
                 self.sum_rows[pos.y as usize] += 1;
                 self.sum_columns[pos.x as usize] += 1;
             }
-            if unsafe { DEBUG } {
-                self.print_grid(pos);
-            }
+            trace!("Grid:{}", self.grid_str(pos));
 
             // Check sum_rows (incremental)
             {
@@ -272,7 +303,7 @@ impl<'a> State<'a> {
                 let rest = (N) - 1 - pos.x;
                 if sum + rest < sum_goal || sum > sum_goal {
                     // Bad: Cannot satisfy sum_row.
-                    dprintln!(" Bad sum_row {sum}..+{rest} not in {sum_goal}");
+                    trace!(" Bad sum_row {sum}..+{rest} not in {sum_goal}");
                     continue 'outer_loop;
                 }
             }
@@ -284,7 +315,7 @@ impl<'a> State<'a> {
                 let rest = (N) - 1 - pos.y;
                 if sum + rest < sum_goal || sum > sum_goal {
                     // Bad: Cannot satisfy sum_columns.
-                    dprintln!(" Bad sum_col {sum}..+{rest} not in {sum_goal}");
+                    trace!(" Bad sum_col {sum}..+{rest} not in {sum_goal}");
                     continue 'outer_loop;
                 }
             }
@@ -309,7 +340,7 @@ impl<'a> State<'a> {
                         }
                         // TODO: Consider other chest locations (is it needed?)
                         if !in_chest_room {
-                            dprintln!(" Bad hallway {pos:?}");
+                            trace!(" Bad hallway {pos:?}");
                             continue 'outer_loop;
                         }
                     }
@@ -334,7 +365,7 @@ impl<'a> State<'a> {
                             }
                         }
                         if known_empty > 1 || known_block == 4 {
-                            dprintln!(" Bad monstor {p:?}");
+                            trace!(" Bad monstor {p:?}");
                             continue 'outer_loop;
                         }
                     }
@@ -348,7 +379,7 @@ impl<'a> State<'a> {
                 for (dx, dy) in [(-1, 0), (0, -1)] {
                     if let Some(p) = pos.checked_add(dx, dy) {
                         if self.goal.get(p) != GoalCell::Monster && self.is_dead_end(p) {
-                            dprintln!(" Bad dead end {p:?}");
+                            trace!(" Bad dead end {p:?}");
                             continue 'outer_loop;
                         }
                     }
@@ -371,7 +402,7 @@ impl<'a> State<'a> {
                     if let Some(cpos) = last_okay_pos {
                         if self.is_chest_room_valid(cpos) {
                             // Still valid - no need to check each position.
-                            dprintln!("  Chest {i} still looks okay");
+                            trace!("  Chest {i} still looks okay");
                             continue;
                         }
                     }
@@ -379,10 +410,10 @@ impl<'a> State<'a> {
                     let mut chest_okay = true;
                     for (dx, dy) in CHEST_CENTER_OFFSETS {
                         let cpos = or!(cpos.checked_add(dx, dy), continue);
-                        dprintln!(" Check chest {i} {cpos:?} offset {dx} {dy}");
+                        trace!(" Check chest {i} {cpos:?} offset {dx} {dy}");
                         chest_okay = false;
                         if self.is_chest_room_valid(cpos) {
-                            dprintln!("  Chest {i} looks okay");
+                            trace!("  Chest {i} looks okay");
                             self.last_chest_okay_positions[i] = Some(cpos);
                             chest_okay = true;
                             break;
@@ -390,19 +421,19 @@ impl<'a> State<'a> {
                     }
 
                     if !chest_okay {
-                        dprintln!(" Bad chest {i} {cpos:?}");
+                        trace!(" Bad chest {i} {cpos:?}");
                         continue 'outer_loop;
                     }
                 }
             }
 
             if let Some(next_pos) = pos.next() {
-                self.search(next_pos)?;
+                self.search(next_pos, budget)?;
             } else if self.is_hallway_connected() {
-                eprintln!("Found solution:");
-                self.solution_count += 1;
-                self.print_grid(pos);
-                if !self.goal.multi_solution {
+                debug!("Found solution");
+                let grid = self.grid.map(|row| row.map(|c| c == StateCell::Wall));
+                self.solutions.push(grid);
+                if !self.multi_solution {
                     return ControlFlow::Break(());
                 }
             }
@@ -414,6 +445,85 @@ impl<'a> State<'a> {
         ControlFlow::Continue(())
     }
 
+    /// Check all rules on a fully decided grid.
+    fn check_rules(&self) -> Result<(), String> {
+        let walls = |p: &Pos| self.get(*p) == StateCell::Wall;
+        let all_pos: Vec<Pos> = (0..N)
+            .flat_map(|y| (0..N).map(move |x| Pos { x, y }))
+            .collect();
+        for i in 0..N {
+            let row_sum = all_pos.iter().filter(|p| p.y == i && walls(p)).count();
+            let goal = self.goal.sum_rows[i as usize] as usize;
+            if row_sum != goal {
+                return Err(format!(
+                    "row {} has {} walls, expected {}",
+                    i + 1,
+                    row_sum,
+                    goal
+                ));
+            }
+            let column_sum = all_pos.iter().filter(|p| p.x == i && walls(p)).count();
+            let goal = self.goal.sum_columns[i as usize] as usize;
+            if column_sum != goal {
+                return Err(format!(
+                    "column {} has {} walls, expected {}",
+                    i + 1,
+                    column_sum,
+                    goal
+                ));
+            }
+        }
+        for &p in &all_pos {
+            let name = match self.goal.get(p) {
+                GoalCell::Monster => "monster",
+                GoalCell::Chest => "chest",
+                GoalCell::Empty => {
+                    if self.is_dead_end(p) {
+                        return Err(format!("dead end at {} has no monster", p));
+                    }
+                    continue;
+                }
+            };
+            if walls(&p) {
+                return Err(format!("wall on the {} at {}", name, p));
+            }
+        }
+        for &p in &self.goal.monster_positions {
+            let empty = DIRECTIONS
+                .iter()
+                .filter_map(|&(dx, dy)| p.checked_add(dx, dy))
+                .filter(|&q| self.get(q) == StateCell::Empty)
+                .count();
+            if empty != 1 {
+                return Err(format!("monster at {} is not in a dead end", p));
+            }
+        }
+        let mut room_centers = Vec::new();
+        for &p in &self.goal.chest_positions {
+            let center = CHEST_CENTER_OFFSETS
+                .iter()
+                .filter_map(|&(dx, dy)| p.checked_add(dx, dy))
+                .find(|&c| self.is_chest_room_valid(c));
+            match center {
+                Some(c) => room_centers.push(c),
+                None => return Err(format!("chest at {} is not in a 3x3 room with one door", p)),
+            }
+        }
+        for p in all_pos.iter().filter(|p| p.x + 1 < N && p.y + 1 < N) {
+            if self.sum_area(p.x, p.y, p.x + 1, p.y + 1) == 0
+                && !room_centers
+                    .iter()
+                    .any(|c| p.x + 1 >= c.x && p.x <= c.x && p.y + 1 >= c.y && p.y <= c.y)
+            {
+                return Err(format!("2x2 open area at {} is not in a treasure room", p));
+            }
+        }
+        if !self.is_hallway_connected() {
+            return Err("empty cells are not connected".to_string());
+        }
+        Ok(())
+    }
+
     fn is_hallway_connected(&self) -> bool {
         // Find an empty cell to start flood fill.
         let mut expected_count = 0;
@@ -446,7 +556,7 @@ impl<'a> State<'a> {
             }
         }
         if actual_count != expected_count {
-            dprintln!(" Not connected: {actual_count} != {expected_count}");
+            trace!(" Not connected: {actual_count} != {expected_count}");
             return false;
         }
         true
@@ -495,7 +605,7 @@ impl<'a> State<'a> {
             for x in left_top.x..=bottom_right.x {
                 let p = Pos { x, y };
                 if self.get(p) == StateCell::Wall {
-                    dprintln!("  Wall at {p:?}");
+                    trace!("  Wall at {p:?}");
                     return false;
                 }
             }
@@ -514,32 +624,256 @@ impl<'a> State<'a> {
         }
         // Too many openings.
         if openings > 1 {
-            dprintln!("  Too many openings {openings}");
+            trace!("  Too many openings {openings}");
             return false;
         }
         // Fully closed.
         if openings == 0 && undecided == 0 {
-            dprintln!("  No openings");
+            trace!("  No openings");
             return false;
         }
         true
     }
 }
 
-pub(crate) fn main() {
-    unsafe {
-        DEBUG = std::env::var_os("D").is_some();
+pub const INFO: GameInfo = GameInfo {
+    name: "dungeons",
+    aliases: &["dungeons-and-diagrams"],
+    description: "Dungeons & Diagrams from Last Call BBS",
+    format: "\
+First line: wall counts of the 8 columns, prefixed by a space.
+Then 8 lines, one per row: the wall count of the row, followed by
+8 cells. 'M' is a monster, 'C' is a chest, '.' is unknown.",
+    example: include_str!("../data/dungeons/45135238.in"),
+};
+
+/// Search options.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Find all solutions instead of stopping at the first one.
+    pub multi_solution: bool,
+}
+
+/// Result of [`Goal::solve`](crate::Solver::solve).
+#[derive(Clone, Debug)]
+pub struct Solution {
+    /// Solutions found. At most one unless `multi_solution` is set.
+    pub grids: Vec<Grid>,
+    pub multi_solution: bool,
+    /// Set if the search stopped early.
+    pub gave_up: Option<GaveUp>,
+    /// If the search gave up, rows of the furthest partial grid reached.
+    /// '?' is undecided.
+    pub partial_grid: Vec<String>,
+    pub stats: Stats,
+}
+
+fn grid_lines(grid: &Grid) -> impl Iterator<Item = String> + '_ {
+    grid.iter()
+        .map(|row| row.iter().map(|&w| if w { '#' } else { '.' }).collect())
+}
+
+impl fmt::Display for Solution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for grid in &self.grids {
+            writeln!(f, "Found solution:")?;
+            for line in grid_lines(grid) {
+                writeln!(f, "{}", line)?;
+            }
+        }
+        if let Some(reason) = self.gave_up {
+            writeln!(f, "Gave up ({}). Furthest partial grid:", reason)?;
+            for line in &self.partial_grid {
+                writeln!(f, "{}", line)?;
+            }
+        }
+        if self.multi_solution {
+            writeln!(f, "Found {} solution(s).", self.grids.len())?;
+        } else if self.grids.is_empty() && self.gave_up.is_none() {
+            writeln!(f, "No solution exists.")?;
+        }
+        Ok(())
+    }
+}
+
+impl crate::Solution for Solution {
+    fn is_solved(&self) -> bool {
+        !self.grids.is_empty()
+    }
+
+    fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    fn gave_up(&self) -> Option<GaveUp> {
+        self.gave_up
+    }
+
+    /// The search is exhaustive, so finishing without a grid proves there is
+    /// none.
+    fn is_unsolvable(&self) -> bool {
+        self.grids.is_empty() && self.gave_up.is_none()
+    }
+
+    /// Walls are '#'. Empty cells are '.'.
+    fn to_json(&self) -> Json {
+        if self.grids.is_empty() && self.gave_up.is_none() {
+            return Json::Null;
+        }
+        let grids: Vec<Json> = self
+            .grids
+            .iter()
+            .map(|g| grid_lines(g).collect::<Vec<_>>().into())
+            .collect();
+        let mut v = Json::object([("grids", Json::Array(grids))]);
+        if self.gave_up.is_some() {
+            v.push("partial_grid", self.partial_grid.clone().into());
+        }
+        v
+    }
+}
+
+impl crate::Solver for Goal {
+    type Options = Options;
+    type Solution = Solution;
+
+    fn parse(input: &str) -> Result<Self, ParseError> {
+        Goal::parse(input)
+    }
+
+    fn solve_with_limits(&self, options: &Options, limits: &Limits) -> Solution {
+        let mut state = State::from_goal(self, options.multi_solution);
+        let _ = state.search(Pos { x: 0, y: 0 }, &mut Budget::new(limits));
+        let partial_grid = match (state.gave_up, state.deepest) {
+            (Some(_), Some((_, grid))) => grid
+                .iter()
+                .map(|row| row.iter().map(|c| c.to_char()).collect())
+                .collect(),
+            _ => Vec::new(),
+        };
+        Solution {
+            grids: state.solutions,
+            multi_solution: options.multi_solution,
+            gave_up: state.gave_up,
+            partial_grid,
+            stats: Stats {
+                searched: state.search_count,
+                cache_hits: 0,
+                memory: 0,
+            },
+        }
     }
-    let mut input = std::io::stdin();
-    let goal = Goal::read_from_ascii(&mut input).unwrap();
-    // dbg!(&goal);
-    let mut state = State::from_goal(&goal);
-    state.search(Pos { x: 0, y: 0 });
-    if goal.multi_solution {
-        let c = state.solution_count;
-        eprintln!("Found {c} solution(s).");
+
+    /// Check the first grid of '#' (wall) and '.' (empty) lines.
+    fn verify(&self, solution: &str) -> Result<String, String> {
+        let lines: Vec<&str> = solution
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && l.chars().all(|c| c == '#' || c == '.'))
+            .take(NS)
+            .collect();
+        if lines.len() < NS {
+            return Err(format!(
+                "expected {} rows of '#' and '.', found {}",
+                NS,
+                lines.len()
+            ));
+        }
+        let mut state = State::from_goal(self, false);
+        for (y, line) in lines.into_iter().enumerate() {
+            if line.len() != NS {
+                return Err(format!("row {} has {} cells", y + 1, line.len()));
+            }
+            for (x, ch) in line.chars().enumerate() {
+                state.grid[y][x] = match ch {
+                    '#' => StateCell::Wall,
+                    _ => StateCell::Empty,
+                };
+            }
+        }
+        state.check_rules()?;
+        Ok("Valid solution.".to_string())
     }
-    eprintln!("Searches: {}", state.search_count);
+}
+
+#[test]
+fn test_solve_data() {
+    use crate::Solver as _;
+    let path = format!("{}/data/dungeons/45135238.in", env!("CARGO_MANIFEST_DIR"));
+    let input = std::fs::read_to_string(path).unwrap();
+    let goal = Goal::parse(&input).unwrap();
+    let solution = goal.solve(&Options::default());
+    assert_eq!(solution.stats.searched, 195);
+    let grid = solution.grids[0];
+    assert_eq!(
+        grid[0],
+        [true, false, false, false, false, false, false, false]
+    );
+    let solution = goal.solve(&Options {
+        multi_solution: true,
+    });
+    assert_eq!(solution.grids.len(), 1);
+}
+
+#[test]
+fn test_limits() {
+    use crate::Solution as _;
+    use crate::Solver as _;
+    let goal = Goal::parse(INFO.example).unwrap();
+    let limits = Limits {
+        max_states: Some(50),
+        ..Default::default()
+    };
+    let solution = goal.solve_with_limits(&Options::default(), &limits);
+    assert!(solution.grids.is_empty());
+    assert_eq!(solution.gave_up, Some(GaveUp::MaxStates));
+    assert_eq!(solution.partial_grid.len(), NS);
+    assert!(!solution.is_unsolvable());
+
+    let path = format!("{}/data/dungeons/f.in", env!("CARGO_MANIFEST_DIR"));
+    let input = std::fs::read_to_string(path).unwrap();
+    let solution = Goal::parse(&input).unwrap().solve(&Options::default());
+    assert!(solution.is_unsolvable());
+}
+
+#[test]
+fn test_parse_errors() {
+    let err = |from: &str, to: &str| {
+        let input = INFO.example.replacen(from, to, 1);
+        Goal::parse(&input).unwrap_err().to_string()
+    };
+    assert_eq!(
+        err("0M......M", "ÖM......M"),
+        "line 4, column 1: expected a wall count, got 'Ö'"
+    );
+    assert_eq!(
+        err("6....M...", "6....M.."),
+        "line 5: row 4 has 7 cells, expected 8"
+    );
+    assert_eq!(
+        err("6....M...", "6....X..."),
+        "line 5, column 6: unknown cell 'X'"
+    );
+    assert_eq!(
+        err(" 61334345", " 6133434"),
+        "line 1: expected 8 column wall counts, found 7"
+    );
+    assert_eq!(err("5.M....M.\n", ""), "expected 8 rows, found 7");
+}
+
+#[test]
+fn test_verify() {
+    use crate::Solver as _;
+    let goal = Goal::parse(INFO.example).unwrap();
+    let solution = goal.solve(&Options::default()).to_string();
+    assert_eq!(goal.verify(&solution), Ok("Valid solution.".to_string()));
+
+    let verify = |from: &str, to: &str| goal.verify(&solution.replacen(from, to, 1));
+    let err = verify("#.......", "#......#").unwrap_err();
+    assert_eq!(err, "row 1 has 2 walls, expected 1");
+    let err = verify("#.######", "##.#####").unwrap_err();
+    assert!(err.starts_with("column 2 has"), "{}", err);
+    assert!(goal.verify("#.......").is_err());
 }
 
 #[test]
@@ -0,0 +1,216 @@
+//! Solvers for a subset of Zachtronics games.
+//!
+//! Each game exposes its puzzle type behind the [`Solver`] trait:
+//! [`cjul::State`], [`cribbage_solitaire::Problem`] and [`dungeons::Goal`].
+
+#![allow(dead_code)]
+
+use std::fmt;
+
+use json::Json;
+use limits::GaveUp;
+use limits::Limits;
+
+pub mod arena;
+pub mod cjul;
+pub mod cribbage_solitaire;
+pub mod dungeons;
+pub mod json;
+pub mod limits;
+pub mod log;
+pub mod progress;
+pub mod util;
+
+/// Static information about a game.
+#[derive(Debug)]
+pub struct GameInfo {
+    /// Canonical name.
+    pub name: &'static str,
+    /// Alternative names.
+    pub aliases: &'static [&'static str],
+    /// One line description.
+    pub description: &'static str,
+    /// Description of the puzzle input format.
+    pub format: &'static str,
+    /// An example puzzle.
+    pub example: &'static str,
+}
+
+/// A puzzle that can be parsed from its text format and solved.
+pub trait Solver: Sized {
+    /// Game specific search options.
+    type Options: Default;
+
+    /// Structured solution. `Display` renders it for humans.
+    type Solution: Solution;
+
+    /// Parse a puzzle.
+    fn parse(input: &str) -> Result<Self, ParseError>;
+
+    /// Search for a solution.
+    fn solve(&self, options: &Self::Options) -> Self::Solution {
+        self.solve_with_limits(options, &Limits::default())
+    }
+
+    /// Search for a solution. Give up if a limit is reached.
+    fn solve_with_limits(&self, options: &Self::Options, limits: &Limits) -> Self::Solution;
+
+    /// Replay a proposed solution written in the same text format as the
+    /// solution output. Return a summary if the solution is valid, or
+    /// describe the first illegal step or failed rule.
+    fn verify(&self, solution: &str) -> Result<String, String>;
+}
+
+/// Result of [`Solver::solve`].
+pub trait Solution: fmt::Display {
+    /// Whether a solution was found.
+    fn is_solved(&self) -> bool;
+
+    /// Statistics of the search.
+    fn stats(&self) -> Stats;
+
+    /// Why the search stopped early, if it did.
+    fn gave_up(&self) -> Option<GaveUp> {
+        None
+    }
+
+    /// Whether the search proved that there is no solution.
+    fn is_unsolvable(&self) -> bool {
+        false
+    }
+
+    /// Score of the solution, for games that have scores.
+    fn score(&self) -> Option<usize> {
+        None
+    }
+
+    /// Number of steps of the solution, for games that have steps.
+    fn step_count(&self) -> Option<usize> {
+        None
+    }
+
+    /// The first step of the solution, for games that have steps.
+    /// `None` if there is no solution or no step is left.
+    fn next_move(&self) -> Option<String> {
+        None
+    }
+
+    /// Structured form of the solution, excluding stats.
+    /// `Json::Null` if there is no solution.
+    fn to_json(&self) -> Json;
+}
+
+/// Error parsing a puzzle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number, if the problem is on a specific line.
+    pub line: Option<usize>,
+    /// 1-based column (in characters), if the problem is at a specific position.
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl ParseError {
+    /// Error about the input as a whole.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            line: None,
+            column: None,
+            message: message.into(),
+        }
+    }
+
+    /// Error about a line. `line` is 0-based.
+    pub fn at_line(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line: Some(line + 1),
+            ..Self::new(message)
+        }
+    }
+
+    /// Error about a position. `line` and `column` are 0-based.
+    pub fn at(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            line: Some(line + 1),
+            column: Some(column + 1),
+            ..Self::new(message)
+        }
+    }
+
+    /// Error about a card rank that appears too many times.
+    /// `rows` are 0-based.
+    pub fn too_many(rank: &str, rows: &[usize]) -> Self {
+        let rows: Vec<String> = rows.iter().map(|r| (r + 1).to_string()).collect();
+        Self::new(format!(
+            "rank {} appears {} times (rows {})",
+            rank,
+            rows.len(),
+            rows.join(", ")
+        ))
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "line {}, column {}: ", line, column)?,
+            (Some(line), None) => write!(f, "line {}: ", line)?,
+            _ => {}
+        }
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Search statistics.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Searched states.
+    pub searched: usize,
+    /// Searches skipped by looking up a cache.
+    pub cache_hits: usize,
+    /// Approximate memory used by the search at the end, in bytes.
+    /// 0 if unknown.
+    pub memory: usize,
+}
+
+impl Stats {
+    pub fn to_json(&self) -> Json {
+        let mut v = Json::object([
+            ("searched", Json::from(self.searched)),
+            ("cache_hits", self.cache_hits.into()),
+        ]);
+        if self.memory > 0 {
+            v.push("memory_bytes", self.memory.into());
+            v.push("states_per_gb", self.states_per_gb().into());
+        }
+        v
+    }
+
+    /// Searched states that fit in 1 GB of memory, at the measured rate.
+    pub fn states_per_gb(&self) -> usize {
+        if self.memory == 0 {
+            return 0;
+        }
+        (self.searched as f64 * (1 << 30) as f64 / self.memory as f64) as usize
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Searched states: {}.", self.searched)?;
+        if self.cache_hits > 0 {
+            write!(f, " Cache hit: {}.", self.cache_hits)?;
+        }
+        if self.memory > 0 {
+            write!(
+                f,
+                " Memory: {:.1} MB ({} states per GB).",
+                self.memory as f64 / (1 << 20) as f64,
+                self.states_per_gb()
+            )?;
+        }
+        Ok(())
+    }
+}
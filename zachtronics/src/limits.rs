@@ -0,0 +1,203 @@
+//! Limits and cancellation of searches.
+
+use std::fmt;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::progress::Progress;
+use crate::progress::Reporter;
+
+/// Limits of a single search. The default has no limits.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// Wall time.
+    pub timeout: Option<Duration>,
+    /// Number of searched states.
+    pub max_states: Option<usize>,
+    /// Approximate memory used by the search, in bytes.
+    pub max_memory: Option<usize>,
+    /// Stop the search from another thread.
+    pub cancel: CancelHandle,
+    /// Receives progress of the search.
+    pub progress: Option<Reporter>,
+}
+
+/// Handle to cancel a running search. Clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Why a search stopped before completion.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GaveUp {
+    Timeout,
+    MaxStates,
+    MaxMemory,
+    Cancelled,
+}
+
+impl GaveUp {
+    /// Short name, used in structured output.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Timeout => "timeout",
+            Self::MaxStates => "max_states",
+            Self::MaxMemory => "max_memory",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl fmt::Display for GaveUp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Timeout => "timed out",
+            Self::MaxStates => "reached the state limit",
+            Self::MaxMemory => "reached the memory limit",
+            Self::Cancelled => "cancelled",
+        })
+    }
+}
+
+/// Tracks a running search against its [`Limits`], and reports its progress.
+pub struct Budget<'a> {
+    limits: &'a Limits,
+    start: Instant,
+    deadline: Option<Instant>,
+    check_count: usize,
+    report_count: usize,
+    next_report: Instant,
+    last_report: Option<Progress>,
+}
+
+impl<'a> Budget<'a> {
+    pub fn new(limits: &'a Limits) -> Self {
+        let start = Instant::now();
+        let interval = limits.progress.as_ref().map(|r| r.interval);
+        Self {
+            limits,
+            start,
+            deadline: limits.timeout.map(|t| start + t),
+            check_count: 0,
+            report_count: 0,
+            next_report: start + interval.unwrap_or_default(),
+            last_report: None,
+        }
+    }
+
+    /// Report progress if a reporter is set and its interval has passed.
+    /// `update` fills in the game specific fields. Cheap enough to be called
+    /// for every state.
+    pub fn report(&mut self, states: usize, update: impl FnOnce(&mut Progress)) {
+        let reporter = match &self.limits.progress {
+            Some(reporter) => reporter,
+            None => return,
+        };
+        self.report_count += 1;
+        if !self.report_count.is_multiple_of(256) {
+            return;
+        }
+        let now = Instant::now();
+        if now < self.next_report {
+            return;
+        }
+        self.next_report = now + reporter.interval;
+        let mut progress = Progress {
+            states,
+            elapsed: now - self.start,
+            ..Default::default()
+        };
+        update(&mut progress);
+        reporter.call(&progress);
+        self.last_report = Some(progress);
+    }
+
+    /// Check limits given the searched state count and estimated memory usage.
+    /// Cheap enough to be called for every state.
+    pub fn check(&mut self, states: usize, memory: usize) -> Result<(), GaveUp> {
+        self.check_count += 1;
+        if matches!(self.limits.max_states, Some(n) if states > n) {
+            return Err(GaveUp::MaxStates);
+        }
+        if matches!(self.limits.max_memory, Some(n) if memory > n) {
+            return Err(GaveUp::MaxMemory);
+        }
+        // Reading the clock is relatively expensive.
+        if self.check_count.is_multiple_of(256) {
+            if self.limits.cancel.is_cancelled() {
+                return Err(GaveUp::Cancelled);
+            }
+            if matches!(self.deadline, Some(t) if Instant::now() >= t) {
+                return Err(GaveUp::Timeout);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Budget<'_> {
+    fn drop(&mut self) {
+        if let (Some(reporter), Some(mut progress)) =
+            (&self.limits.progress, self.last_report.take())
+        {
+            progress.elapsed = self.start.elapsed();
+            progress.done = true;
+            reporter.call(&progress);
+        }
+    }
+}
+
+#[test]
+fn test_budget() {
+    let limits = Limits {
+        max_states: Some(10),
+        ..Default::default()
+    };
+    let mut budget = Budget::new(&limits);
+    assert_eq!(budget.check(10, 0), Ok(()));
+    assert_eq!(budget.check(11, 0), Err(GaveUp::MaxStates));
+
+    let limits = Limits::default();
+    let mut budget = Budget::new(&limits);
+    limits.cancel.clone().cancel();
+    let result = (0..256).map(|_| budget.check(0, 0)).last().unwrap();
+    assert_eq!(result, Err(GaveUp::Cancelled));
+}
+
+#[test]
+fn test_report() {
+    use std::sync::Mutex;
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let limits = Limits {
+        progress: Some(Reporter::new(Duration::ZERO, {
+            let reports = reports.clone();
+            move |p| reports.lock().unwrap().push((p.states, p.depth, p.done))
+        })),
+        ..Default::default()
+    };
+    let mut budget = Budget::new(&limits);
+    for i in 0..512 {
+        budget.report(i, |p| p.depth = Some(i / 2));
+    }
+    drop(budget);
+    assert_eq!(
+        *reports.lock().unwrap(),
+        [
+            (255, Some(127), false),
+            (511, Some(255), false),
+            (511, Some(255), true)
+        ]
+    );
+}
@@ -0,0 +1,197 @@
+//! Leveled logging with per-module filters.
+//!
+//! Messages are written to stderr, or to a file set by [`set_output`].
+//! Use the [`trace!`](crate::trace), [`debug!`](crate::debug) and
+//! [`info!`](crate::info) macros, or [`log!`](crate::log) for other levels.
+
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+            Self::Trace => "trace",
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let all = [
+            Self::Error,
+            Self::Warn,
+            Self::Info,
+            Self::Debug,
+            Self::Trace,
+        ];
+        all.into_iter()
+            .find(|l| l.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown log level: {:?}", s))
+    }
+}
+
+/// Maximum level per module. Modules are matched by the last component of
+/// their path, like `cjul` or `dungeons`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Filter {
+    /// Level of modules without a directive.
+    pub default: Level,
+    pub modules: Vec<(String, Level)>,
+}
+
+impl Filter {
+    /// Log `level` and above for all modules.
+    pub fn all(level: Level) -> Self {
+        Self {
+            default: level,
+            modules: Vec::new(),
+        }
+    }
+
+    /// Parse a comma separated list of `module=level` or `level` (default for
+    /// all modules), like `dungeons=trace,cjul=info`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut filter = Self::all(Level::Warn);
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    let level = level.trim().parse()?;
+                    filter.modules.push((module.trim().to_string(), level));
+                }
+                None => filter.default = directive.parse()?,
+            }
+        }
+        Ok(filter)
+    }
+
+    fn level(&self, module_path: &str) -> Level {
+        let module = module_path.rsplit("::").next().unwrap_or(module_path);
+        // Later directives take precedence.
+        match self.modules.iter().rev().find(|(m, _)| m == module) {
+            Some((_, level)) => *level,
+            None => self.default,
+        }
+    }
+
+    fn max_level(&self) -> Level {
+        let levels = self.modules.iter().map(|(_, l)| *l);
+        levels.fold(self.default, Level::max)
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::all(Level::Warn)
+    }
+}
+
+struct Logger {
+    filter: Filter,
+    file: Option<File>,
+}
+
+static LOGGER: Mutex<Logger> = Mutex::new(Logger {
+    filter: Filter {
+        default: Level::Warn,
+        modules: Vec::new(),
+    },
+    file: None,
+});
+
+// Fast path. Maximum level of any module.
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+
+/// Replace the filter.
+pub fn set_filter(filter: Filter) {
+    let mut logger = LOGGER.lock().unwrap();
+    MAX_LEVEL.store(filter.max_level() as u8, Ordering::Relaxed);
+    logger.filter = filter;
+}
+
+/// Write to a file instead of stderr. `None` writes to stderr.
+pub fn set_output(file: Option<File>) {
+    LOGGER.lock().unwrap().file = file;
+}
+
+/// Whether messages of `level` from `module_path` are written.
+pub fn enabled(module_path: &str, level: Level) -> bool {
+    if level as u8 > MAX_LEVEL.load(Ordering::Relaxed) {
+        return false;
+    }
+    level <= LOGGER.lock().unwrap().filter.level(module_path)
+}
+
+/// Write a message. Use the macros instead, which check [`enabled`] first.
+pub fn write(module_path: &str, level: Level, args: fmt::Arguments<'_>) {
+    let module = module_path.rsplit("::").next().unwrap_or(module_path);
+    let line = format!("[{} {}] {}\n", level.name().to_uppercase(), module, args);
+    let mut logger = LOGGER.lock().unwrap();
+    let _ = match &mut logger.file {
+        Some(file) => file.write_all(line.as_bytes()),
+        None => std::io::stderr().write_all(line.as_bytes()),
+    };
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($t:tt)*) => {
+        if $crate::log::enabled(module_path!(), $level) {
+            $crate::log::write(module_path!(), $level, format_args!($($t)*));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($t:tt)*) => {
+        $crate::log!($crate::log::Level::Info, $($t)*)
+    };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($t:tt)*) => {
+        $crate::log!($crate::log::Level::Debug, $($t)*)
+    };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($t:tt)*) => {
+        $crate::log!($crate::log::Level::Trace, $($t)*)
+    };
+}
+
+#[test]
+fn test_filter() {
+    let filter = Filter::parse("dungeons=trace, cjul=info").unwrap();
+    assert_eq!(filter.level("zachtronics::dungeons"), Level::Trace);
+    assert_eq!(filter.level("zachtronics::cjul"), Level::Info);
+    assert_eq!(filter.level("zachtronics::limits"), Level::Warn);
+    assert_eq!(filter.max_level(), Level::Trace);
+    let filter = Filter::parse("debug,cjul=error").unwrap();
+    assert_eq!(filter.level("zachtronics::dungeons"), Level::Debug);
+    assert_eq!(filter.level("zachtronics::cjul"), Level::Error);
+    assert!(Filter::parse("cjul=loud").is_err());
+}
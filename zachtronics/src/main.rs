@@ -1,41 +1,439 @@
-#![allow(dead_code)]
-
 use std::env;
+use std::fs;
+use std::io::Read;
+use std::process;
+
+use zachtronics::json::Json;
+use zachtronics::log;
+use zachtronics::progress::Reporter;
+use zachtronics::util;
+use zachtronics::Solution;
+
+use cli::Flag;
+use cli::Matches;
+use registry::Game;
+
+mod batch;
+mod cli;
+mod deals;
+mod registry;
+mod tune;
 
-mod cjul;
-mod cribbage_solitaire;
-mod dungeons;
-pub(crate) mod util;
+fn usage() -> String {
+    let mut out = String::from(
+        "\
+Usage: zachtronics <GAME> [OPTIONS] < PUZZLE
+       zachtronics batch <GAME> [OPTIONS] <PATH>...
+       zachtronics verify <GAME> [OPTIONS] <SOLUTION> < PUZZLE
+       zachtronics hint <GAME> [OPTIONS] < POSITION
+       zachtronics tune [OPTIONS] [PATH]...
+       zachtronics deal [OPTIONS]
+       zachtronics stats [OPTIONS]
+       zachtronics rate [OPTIONS] < DEAL
+       zachtronics moves [OPTIONS] < POSITION
+       zachtronics tablebase [OPTIONS] --output FILE
+       zachtronics list
+       zachtronics help [GAME]
+
+Games:
+",
+    );
+    for game in registry::GAMES {
+        out += &format!("  {}\n", game.info.name);
+        for line in cli::flags_help(&[game.flags]).lines() {
+            out += &format!("  {}\n", line);
+        }
+    }
+    out += "\nSolve options:\n";
+    out += &cli::flags_help(&[SOLVE_FLAGS]);
+    out += "\nBatch options:\n";
+    out += &cli::flags_help(&[batch::FLAGS]);
+    out += "\nTune options (cjul search order):\n";
+    out += &cli::flags_help(&[tune::FLAGS]);
+    out += "\nDeal options (random cjul deal):\n";
+    out += &cli::flags_help(&[deals::DEAL_FLAGS]);
+    out += "\nStats options (solve random cjul deals, with the cjul options):\n";
+    out += &cli::flags_help(&[deals::STATS_FLAGS]);
+    out += "\nRate and moves take the cjul options. Rate rates the difficulty of a\n";
+    out += "deal. Moves ranks all moves from a position by whether they still win.\n";
+    out += "\nTablebase options (cjul endgame, for --tablebase):\n";
+    out += &cli::flags_help(&[deals::TABLEBASE_FLAGS]);
+    out += "\nLimits (each search of solve, batch, tune, stats, rate and moves):\n";
+    out += &cli::flags_help(&[registry::LIMIT_FLAGS]);
+    out += "\nOptions:\n";
+    out += &cli::flags_help(&[cli::GLOBAL_FLAGS]);
+    out
+}
 
-const SUPPORTED_GAMES: &[(&str, fn())] = &[
-    ("cjul", cjul::main),
-    ("cribbage", cribbage_solitaire::main),
-    ("dungeons", dungeons::main),
+fn game_usage(game: &Game) -> String {
+    let info = game.info;
+    let mut out = format!("{}\n\n", info.description);
+    out += &format!("Usage: zachtronics {} [OPTIONS] < PUZZLE\n", info.name);
+    if !info.aliases.is_empty() {
+        out += &format!("Aliases: {}\n", info.aliases.join(", "));
+    }
+    out += "\nOptions:\n";
+    out += &cli::flags_help(&[
+        game.flags,
+        SOLVE_FLAGS,
+        registry::LIMIT_FLAGS,
+        cli::GLOBAL_FLAGS,
+    ]);
+    out += &format!("\nInput format:\n{}", indent(info.format));
+    out += &format!("\nExample:\n{}", indent(info.example));
+    out
+}
+
+fn indent(s: &str) -> String {
+    s.lines().map(|l| format!("  {}\n", l)).collect()
+}
+
+const SOLVE_FLAGS: &[Flag] = &[
+    Flag {
+        name: "--format",
+        short: Some("-f"),
+        value: Some("text|json"),
+        env: None,
+        help: "Output format of the solution",
+    },
+    Flag {
+        name: "--no-progress",
+        short: None,
+        value: None,
+        env: None,
+        help: "Do not report progress of long searches on stderr",
+    },
 ];
 
-fn common_prefix(a: &str, b: &str) -> usize {
-    a.chars().zip(b.chars()).take_while(|(a, b)| a == b).count()
+/// Exit status when the search proved there is no solution.
+const EXIT_UNSOLVABLE: i32 = 2;
+/// Exit status when the search gave up before finding a solution.
+const EXIT_GAVE_UP: i32 = 3;
+
+fn read_stdin() -> Result<String, String> {
+    let mut input = String::new();
+    std::io::stdin()
+        .read_to_string(&mut input)
+        .map_err(|e| e.to_string())?;
+    Ok(input)
+}
+
+/// Take the first positional argument, skipping the values of flags, like
+/// `3` in `-q 3 cribbage`.
+fn take_positional(args: &mut Vec<String>) -> Result<Option<String>, String> {
+    // The command is not known yet, so a flag is looked up in all groups.
+    let mut flag_groups = vec![cli::GLOBAL_FLAGS, SOLVE_FLAGS, registry::LIMIT_FLAGS];
+    flag_groups.extend(registry::GAMES.iter().map(|g| g.flags));
+    flag_groups.extend([
+        batch::FLAGS,
+        tune::FLAGS,
+        deals::DEAL_FLAGS,
+        deals::STATS_FLAGS,
+        deals::TABLEBASE_FLAGS,
+    ]);
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        if !arg.starts_with('-') {
+            return Ok(Some(args.remove(i)));
+        }
+        let flags: Vec<&cli::Flag> = flag_groups
+            .iter()
+            .flat_map(|g| g.iter())
+            .filter(|f| f.name == arg || f.short == Some(arg))
+            .collect();
+        let takes_value = flags.first().is_some_and(|f| f.value.is_some());
+        if flags.iter().any(|f| f.value.is_some() != takes_value) {
+            // Like `-o`, which is `--optimal` for cjul and `--output FILE` for
+            // tune. Whether the next argument is the command is unknown.
+            let mut names: Vec<&str> = flags.iter().map(|f| f.name).collect();
+            names.sort();
+            names.dedup();
+            return Err(format!(
+                "{} before the command could be {}; put it after the command",
+                arg,
+                names.join(" or ")
+            ));
+        }
+        i += if takes_value { 2 } else { 1 };
+    }
+    Ok(None)
+}
+
+/// Parse flags for a game command. Return `None` if `--help` was handled.
+fn parse_game_args(
+    game: &Game,
+    args: &[String],
+    extra_flags: &[&'static [Flag]],
+    skip_env: &[&str],
+) -> Result<Option<Matches>, String> {
+    let mut flag_groups = vec![cli::GLOBAL_FLAGS, game.flags];
+    flag_groups.extend_from_slice(extra_flags);
+    let mut m = cli::parse(args, &flag_groups)?;
+    if m.flag("--help") {
+        print!("{}", game_usage(game));
+        return Ok(None);
+    }
+    m.apply_env_fallback(&flag_groups, skip_env);
+    init_log(&m)?;
+    util::set_verbose(m.flag("--verbose"));
+    Ok(Some(m))
+}
+
+/// Parse flags for a command that is not for a single game. Return `None`
+/// if `--help` was handled.
+fn parse_command_args(
+    args: &[String],
+    extra_flags: &[&'static [Flag]],
+) -> Result<Option<Matches>, String> {
+    let mut flag_groups = vec![cli::GLOBAL_FLAGS];
+    flag_groups.extend_from_slice(extra_flags);
+    let m = cli::parse(args, &flag_groups)?;
+    if m.flag("--help") {
+        print!("{}", usage());
+        return Ok(None);
+    }
+    init_log(&m)?;
+    util::set_verbose(m.flag("--verbose"));
+    Ok(Some(m))
+}
+
+/// Set up logging from `--debug`, `--log` and `--log-file`.
+fn init_log(m: &Matches) -> Result<(), String> {
+    let mut filter = match m.value("--log") {
+        Some(spec) => log::Filter::parse(spec)?,
+        None => log::Filter::default(),
+    };
+    if m.flag("--debug") {
+        filter.default = log::Level::Trace;
+    }
+    // Accept game names and aliases.
+    for (name, _) in &mut filter.modules {
+        if let Ok(game) = registry::find(name) {
+            *name = game.log_target.to_string();
+        }
+    }
+    log::set_filter(filter);
+    if let Some(path) = m.value("--log-file") {
+        let file = fs::File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        log::set_output(Some(file));
+    }
+    Ok(())
+}
+
+/// Return the exit status.
+fn try_main() -> Result<i32, String> {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let (name, name_from_env) = match take_positional(&mut args)? {
+        Some(name) => (name, false),
+        None => match env::var("M") {
+            Ok(name) => {
+                eprintln!("Warning: selecting the game by $M is deprecated.");
+                (name, true)
+            }
+            Err(_) => (String::new(), false),
+        },
+    };
+    match name.as_str() {
+        "" | "help" => {
+            let m = cli::parse(&args, &[cli::GLOBAL_FLAGS])?;
+            match m.positional.first() {
+                Some(name) => print!("{}", game_usage(registry::find(name)?)),
+                None if name.is_empty() && !m.flag("--help") => {
+                    return Err(format!("missing game name\n\n{}", usage()));
+                }
+                None => print!("{}", usage()),
+            }
+            return Ok(0);
+        }
+        "list" => {
+            cli::parse(&args, &[cli::GLOBAL_FLAGS])?;
+            print!("{}", registry::list());
+            return Ok(0);
+        }
+        "tune" => {
+            return match parse_command_args(&args, &[tune::FLAGS, registry::LIMIT_FLAGS])? {
+                Some(m) => tune::run(&m).map(|_| 0),
+                None => Ok(0),
+            };
+        }
+        "deal" => {
+            return match parse_command_args(&args, &[deals::DEAL_FLAGS])? {
+                Some(m) => deals::deal(&m).map(|_| 0),
+                None => Ok(0),
+            };
+        }
+        "rate" => {
+            let game = registry::find("cjul")?;
+            return match parse_command_args(&args, &[game.flags, registry::LIMIT_FLAGS])? {
+                Some(m) => deals::rate(&m, &read_stdin()?).map(|_| 0),
+                None => Ok(0),
+            };
+        }
+        "moves" => {
+            let game = registry::find("cjul")?;
+            return match parse_command_args(&args, &[game.flags, registry::LIMIT_FLAGS])? {
+                Some(m) => deals::moves(&m, &read_stdin()?).map(|_| 0),
+                None => Ok(0),
+            };
+        }
+        "tablebase" => {
+            return match parse_command_args(&args, &[deals::TABLEBASE_FLAGS])? {
+                Some(m) => deals::tablebase(&m).map(|_| 0),
+                None => Ok(0),
+            };
+        }
+        "stats" => {
+            let game = registry::find("cjul")?;
+            let flags = [game.flags, deals::STATS_FLAGS, registry::LIMIT_FLAGS];
+            return match parse_command_args(&args, &flags)? {
+                Some(m) => deals::stats(game, &m).map(|_| 0),
+                None => Ok(0),
+            };
+        }
+        "batch" => {
+            let name = take_positional(&mut args)?.ok_or("missing game name")?;
+            let game = registry::find(&name)?;
+            let m = match parse_game_args(game, &args, &[batch::FLAGS, registry::LIMIT_FLAGS], &[])?
+            {
+                Some(m) => m,
+                None => return Ok(0),
+            };
+            return batch::run(game, &m).map(|_| 0);
+        }
+        "verify" => {
+            let name = take_positional(&mut args)?.ok_or("missing game name")?;
+            let game = registry::find(&name)?;
+            let m = match parse_game_args(game, &args, &[], &[])? {
+                Some(m) => m,
+                None => return Ok(0),
+            };
+            let path = match &m.positional[..] {
+                [path] => path,
+                _ => return Err("expect exactly one solution file".to_string()),
+            };
+            let solution = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            let input = read_stdin()?;
+            let summary = (game.verify)(&input, &solution, &m)?;
+            println!("{}", summary);
+            return Ok(0);
+        }
+        "hint" => {
+            let name = take_positional(&mut args)?.ok_or("missing game name")?;
+            let game = registry::find(&name)?;
+            if !game.hint {
+                return Err(format!("hints are not supported for {}", game.info.name));
+            }
+            let m = match parse_game_args(game, &args, &[registry::LIMIT_FLAGS], &[])? {
+                Some(m) => m,
+                None => return Ok(0),
+            };
+            if let Some(arg) = m.positional.first() {
+                return Err(format!("unexpected argument: {}", arg));
+            }
+            let input = read_stdin()?;
+            let limits = registry::limits(&m)?;
+            let solution = (game.solve)(&input, &m, &limits)?;
+            return Ok(print_hint(solution.as_ref()));
+        }
+        _ => {}
+    }
+
+    let game = registry::find(&name)?;
+    // $M selected the game. Do not treat it as --all-solutions.
+    let skip_env: &[&str] = if name_from_env { &["M"] } else { &[] };
+    let m = match parse_game_args(game, &args, &[SOLVE_FLAGS, registry::LIMIT_FLAGS], skip_env)? {
+        Some(m) => m,
+        None => return Ok(0),
+    };
+    if let Some(arg) = m.positional.first() {
+        return Err(format!("unexpected argument: {}", arg));
+    }
+    let json = match m.value("--format") {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(format) => return Err(format!("unsupported format: {}", format)),
+    };
+
+    eprintln!("Selected game: {}", game.info.name);
+    let input = read_stdin()?;
+    let mut limits = registry::limits(&m)?;
+    if !m.flag("--no-progress") {
+        limits.progress = Some(Reporter::stderr());
+    }
+    let solution = (game.solve)(&input, &m, &limits)?;
+    if json {
+        let v = Json::object([
+            ("game", Json::from(game.info.name)),
+            ("solved", solution.is_solved().into()),
+            ("gave_up", solution.gave_up().map(|r| r.name()).into()),
+            ("unsolvable", solution.is_unsolvable().into()),
+            ("solution", solution.to_json()),
+            ("stats", solution.stats().to_json()),
+        ]);
+        println!("{}", v);
+    } else {
+        print!("{}", solution);
+        eprintln!("{}", solution.stats());
+    }
+    Ok(exit_status(solution.as_ref()))
+}
+
+fn exit_status(solution: &dyn Solution) -> i32 {
+    if solution.is_solved() {
+        0
+    } else if solution.is_unsolvable() {
+        EXIT_UNSOLVABLE
+    } else {
+        // Not found and not proved impossible: the search gave up.
+        EXIT_GAVE_UP
+    }
+}
+
+/// Print the next move of a solution. Return the exit status.
+fn print_hint(solution: &dyn Solution) -> i32 {
+    match (solution.next_move(), solution.step_count()) {
+        (Some(m), Some(n)) => println!("Next: {}\n{} moves left.", m, n),
+        _ if solution.is_solved() => println!("Already solved."),
+        _ if solution.is_unsolvable() => println!("The position is lost. No solution exists."),
+        _ => match solution.gave_up() {
+            Some(reason) => println!("No hint: gave up ({}) before finding a solution.", reason),
+            None => println!("No hint: no solution found."),
+        },
+    }
+    exit_status(solution)
 }
 
 fn main() {
-    let name = env::args()
-        .nth(1)
-        .or_else(|| env::var("M").ok())
-        .unwrap_or_default();
-    let best = SUPPORTED_GAMES
-        .iter()
-        .max_by_key(|(n, _)| common_prefix(&name, n));
-    match best {
-        None => {
-            eprintln!("Ambigious or unsupported game name: {:?}", name);
-            eprintln!(
-                "Supported game names: {:?}",
-                SUPPORTED_GAMES.iter().map(|v| v.0).collect::<Vec<_>>()
-            );
-        }
-        Some((name, entry_point)) => {
-            eprintln!("Selected game: {}", name);
-            entry_point();
+    match try_main() {
+        Ok(0) => {}
+        Ok(status) => process::exit(status),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
         }
     }
 }
+
+#[test]
+fn test_take_positional() {
+    let args = |a: &[&str]| -> Vec<String> { a.iter().map(|s| s.to_string()).collect() };
+    let mut a = args(&["-q", "3", "--debug", "-a", "cribbage", "x"]);
+    assert_eq!(take_positional(&mut a), Ok(Some("cribbage".to_string())));
+    assert_eq!(a, ["-q", "3", "--debug", "-a", "x"]);
+    assert_eq!(take_positional(&mut a), Ok(Some("x".to_string())));
+    assert_eq!(take_positional(&mut a), Ok(None));
+
+    let mut a = args(&["--output", "tb.bin", "-j", "2", "tablebase", "--loose", "2"]);
+    assert_eq!(take_positional(&mut a), Ok(Some("tablebase".to_string())));
+    let mut a = args(&["--optimal", "cjul"]);
+    assert_eq!(take_positional(&mut a), Ok(Some("cjul".to_string())));
+    let mut a = args(&["-o", "tb.bin", "tablebase"]);
+    assert_eq!(
+        take_positional(&mut a),
+        Err(
+            "-o before the command could be --optimal or --output; put it after the command"
+                .to_string()
+        )
+    );
+}
@@ -0,0 +1,114 @@
+//! Progress reporting of long searches.
+
+use std::fmt;
+use std::io::IsTerminal;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Snapshot of a running search. Fields a game cannot provide are `None`.
+#[derive(Clone, Debug, Default)]
+pub struct Progress {
+    /// Searched states so far.
+    pub states: usize,
+    pub elapsed: Duration,
+    /// Best heuristic score reached so far.
+    pub best_score: Option<usize>,
+    /// Depth of the state being searched.
+    pub depth: Option<usize>,
+    /// States waiting to be searched.
+    pub frontier: Option<usize>,
+    /// Set on the last report, after the search ends.
+    pub done: bool,
+}
+
+impl Progress {
+    pub fn states_per_sec(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.states as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} states ({:.0}/s)", self.states, self.states_per_sec())?;
+        if let Some(v) = self.best_score {
+            write!(f, ", best score {}", v)?;
+        }
+        if let Some(v) = self.depth {
+            write!(f, ", depth {}", v)?;
+        }
+        if let Some(v) = self.frontier {
+            write!(f, ", frontier {}", v)?;
+        }
+        write!(f, ", {:.1}s", self.elapsed.as_secs_f64())
+    }
+}
+
+type Callback = dyn Fn(&Progress) + Send + Sync;
+
+/// Receives [`Progress`] of a search at most once per `interval`, and once
+/// more with `done` set when the search ends.
+#[derive(Clone)]
+pub struct Reporter {
+    pub interval: Duration,
+    callback: Arc<Callback>,
+}
+
+impl Reporter {
+    pub fn new(interval: Duration, callback: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        Self {
+            interval,
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// A live line if stderr is a terminal. Otherwise a log line every 10 seconds.
+    pub fn stderr() -> Self {
+        if std::io::stderr().is_terminal() {
+            Self::new(Duration::from_millis(200), |p| {
+                if p.done {
+                    eprint!("\r\x1b[K");
+                } else {
+                    eprint!("\r{}\x1b[K", p);
+                }
+            })
+        } else {
+            Self::new(Duration::from_secs(10), |p| {
+                if !p.done {
+                    eprintln!("Progress: {}", p);
+                }
+            })
+        }
+    }
+
+    pub(crate) fn call(&self, progress: &Progress) {
+        (self.callback)(progress)
+    }
+}
+
+impl fmt::Debug for Reporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reporter")
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn test_progress_display() {
+    let p = Progress {
+        states: 3000,
+        elapsed: Duration::from_secs(2),
+        best_score: Some(12),
+        frontier: Some(40),
+        ..Default::default()
+    };
+    assert_eq!(
+        p.to_string(),
+        "3000 states (1500/s), best score 12, frontier 40, 2.0s"
+    );
+}
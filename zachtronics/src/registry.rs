@@ -0,0 +1,381 @@
+//! Supported games and their command line options.
+
+use zachtronics::cjul;
+use zachtronics::cribbage_solitaire;
+use zachtronics::dungeons;
+use zachtronics::limits::Limits;
+use zachtronics::GameInfo;
+use zachtronics::Solution;
+use zachtronics::Solver;
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::cli::Flag;
+use crate::cli::Matches;
+
+/// Parse and solve a puzzle, with options from the command line.
+type EntryPoint = fn(&str, &Matches, &Limits) -> Result<Box<dyn Solution>, String>;
+
+/// Parse a puzzle and verify a solution, with options from the command line.
+type Verify = fn(&str, &str, &Matches) -> Result<String, String>;
+
+/// A game that can be selected from the command line.
+pub struct Game {
+    pub info: &'static GameInfo,
+    /// Module name, used to filter log messages.
+    pub log_target: &'static str,
+    /// Game specific flags.
+    pub flags: &'static [Flag],
+    pub solve: EntryPoint,
+    pub verify: Verify,
+    /// Whether solutions have steps to give as hints.
+    pub hint: bool,
+}
+
+/// Limits accepted by all games.
+pub const LIMIT_FLAGS: &[Flag] = &[
+    Flag {
+        name: "--timeout",
+        short: Some("-t"),
+        value: Some("SECS"),
+        env: None,
+        help: "Give up after the given wall time",
+    },
+    Flag {
+        name: "--max-states",
+        short: None,
+        value: Some("N"),
+        env: None,
+        help: "Give up after searching N states",
+    },
+    Flag {
+        name: "--max-memory",
+        short: None,
+        value: Some("MB"),
+        env: None,
+        help: "Give up when the search uses about MB megabytes",
+    },
+];
+
+const CJUL_FLAGS: &[Flag] = &[
+    Flag {
+        name: "--optimal",
+        short: Some("-o"),
+        value: None,
+        env: None,
+        help: "Find the fewest moves. Slower",
+    },
+    Flag {
+        name: "--threads",
+        short: None,
+        value: Some("N"),
+        env: None,
+        help: "Number of threads to search on. Default: 1",
+    },
+    Flag {
+        name: "--deterministic",
+        short: None,
+        value: None,
+        env: None,
+        help: "With --threads, find the same solution as a single thread",
+    },
+    Flag {
+        name: "--exhaustive",
+        short: None,
+        value: None,
+        env: None,
+        help: "Search all reachable states, and count them",
+    },
+    Flag {
+        name: "--rules",
+        short: None,
+        value: Some("SPEC"),
+        env: None,
+        help:
+            "Rule variant, like \"hard\" (no free slots) or \"columns=5,ranks=6-K,copies=3,run=8\"",
+    },
+    Flag {
+        name: "--notation",
+        short: None,
+        value: Some("latin|cyrillic|english"),
+        env: None,
+        help: "Names of the face cards in the output: V D K T, В Д К Т or J Q K A",
+    },
+    Flag {
+        name: "--heuristic",
+        short: None,
+        value: Some("FILE"),
+        env: None,
+        help: "Load the weights of the search order from a profile, as written by tune",
+    },
+    Flag {
+        name: "--spill",
+        short: None,
+        value: Some("DIR"),
+        env: None,
+        help: "Keep searched states in temporary files in DIR. Slower, but fits more",
+    },
+    Flag {
+        name: "--shorten",
+        short: None,
+        value: Some("SECS"),
+        env: None,
+        help: "Spend up to SECS shortening the solution found",
+    },
+    Flag {
+        name: "--tablebase",
+        short: None,
+        value: Some("FILE"),
+        env: None,
+        help: "Finish from an endgame position of a tablebase, as written by tablebase",
+    },
+    Flag {
+        name: "--keep-deadlocks",
+        short: None,
+        value: None,
+        env: None,
+        help: "Search states that are found lost instead of pruning them",
+    },
+];
+
+const CRIBBAGE_FLAGS: &[Flag] = &[Flag {
+    name: "--quality",
+    short: Some("-q"),
+    value: Some("0-10"),
+    env: Some("Q"),
+    help: "Search quality. 10 finds the best score. Lower is faster",
+}];
+
+const DUNGEONS_FLAGS: &[Flag] = &[Flag {
+    name: "--all-solutions",
+    short: Some("-a"),
+    value: None,
+    env: Some("M"),
+    help: "Find all solutions instead of stopping at the first one",
+}];
+
+pub const GAMES: &[Game] = &[
+    Game {
+        info: &cjul::INFO,
+        log_target: "cjul",
+        flags: CJUL_FLAGS,
+        solve: |s, m, l| {
+            let options = cjul_options(m)?;
+            let state = cjul::State::parse_with_rules(s, &options.rules)
+                .map_err(|e| format!("invalid puzzle: {}", e))?;
+            Ok(Box::new(state.solve_with_limits(&options, l)))
+        },
+        verify: |s, solution, m| {
+            let rules = cjul_rules(m)?;
+            let state = cjul::State::parse_with_rules(s, &rules)
+                .map_err(|e| format!("invalid puzzle: {}", e))?;
+            state.verify_with_rules(solution, &rules, cjul_notation(m)?)
+        },
+        hint: true,
+    },
+    Game {
+        info: &cribbage_solitaire::INFO,
+        log_target: "cribbage_solitaire",
+        flags: CRIBBAGE_FLAGS,
+        solve: |s, m, l| {
+            let mut options = cribbage_solitaire::Options::default();
+            if let Some(quality) = m.parse_value::<u8>("--quality")? {
+                if quality > 10 {
+                    return Err(format!("quality {} is out of range 0-10", quality));
+                }
+                options.quality = quality;
+            }
+            solve::<cribbage_solitaire::Problem>(s, l, options)
+        },
+        verify: verify::<cribbage_solitaire::Problem>,
+        hint: false,
+    },
+    Game {
+        info: &dungeons::INFO,
+        log_target: "dungeons",
+        flags: DUNGEONS_FLAGS,
+        solve: |s, m, l| {
+            let options = dungeons::Options {
+                multi_solution: m.flag("--all-solutions"),
+            };
+            solve::<dungeons::Goal>(s, l, options)
+        },
+        verify: verify::<dungeons::Goal>,
+        hint: false,
+    },
+];
+
+fn solve<S: Solver>(
+    input: &str,
+    limits: &Limits,
+    options: S::Options,
+) -> Result<Box<dyn Solution>, String>
+where
+    S::Solution: 'static,
+{
+    let puzzle = S::parse(input).map_err(|e| format!("invalid puzzle: {}", e))?;
+    Ok(Box::new(puzzle.solve_with_limits(&options, limits)))
+}
+
+/// Limits from `LIMIT_FLAGS`.
+pub fn limits(m: &Matches) -> Result<Limits, String> {
+    Ok(Limits {
+        timeout: parse_secs(m, "--timeout")?,
+        max_states: m.parse_value("--max-states")?,
+        max_memory: m
+            .parse_value::<usize>("--max-memory")?
+            .map(|mb| mb.saturating_mul(1 << 20)),
+        ..Default::default()
+    })
+}
+
+/// A positive number of seconds, like `--timeout`.
+fn parse_secs(m: &Matches, name: &str) -> Result<Option<Duration>, String> {
+    match m.parse_value::<f64>(name)? {
+        Some(secs) if !(secs > 0.0 && secs.is_finite()) => Err(format!(
+            "invalid {}: {}",
+            name.trim_start_matches('-'),
+            secs
+        )),
+        secs => Ok(secs.map(Duration::from_secs_f64)),
+    }
+}
+
+pub fn cjul_rules(m: &Matches) -> Result<cjul::Rules, String> {
+    match m.value("--rules") {
+        Some(spec) => spec.parse(),
+        None => Ok(cjul::Rules::default()),
+    }
+}
+
+/// Options from `CJUL_FLAGS`.
+pub fn cjul_options(m: &Matches) -> Result<cjul::Options, String> {
+    Ok(cjul::Options {
+        optimal: m.flag("--optimal"),
+        threads: m.parse_value("--threads")?.unwrap_or(1),
+        deterministic: m.flag("--deterministic"),
+        exhaustive: m.flag("--exhaustive"),
+        rules: cjul_rules(m)?,
+        heuristic: cjul_heuristic(m)?,
+        shorten: parse_secs(m, "--shorten")?,
+        spill_dir: m.value("--spill").map(PathBuf::from),
+        tablebase: cjul_tablebase(m)?,
+        keep_deadlocks: m.flag("--keep-deadlocks"),
+        notation: cjul_notation(m)?,
+    })
+}
+
+/// The tablebase of `--tablebase`. Loaded once, for solving many deals.
+fn cjul_tablebase(m: &Matches) -> Result<Option<Arc<cjul::Tablebase>>, String> {
+    static LOADED: Mutex<Option<(String, Arc<cjul::Tablebase>)>> = Mutex::new(None);
+    let path = match m.value("--tablebase") {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let mut loaded = LOADED.lock().unwrap();
+    if let Some((p, tablebase)) = &*loaded {
+        if p == path {
+            return Ok(Some(tablebase.clone()));
+        }
+    }
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let tablebase = cjul::Tablebase::from_bytes(&bytes).map_err(|e| format!("{}: {}", path, e))?;
+    if tablebase.rules() != &cjul_rules(m)? {
+        return Err(format!(
+            "{}: built for other rules: {}",
+            path,
+            tablebase.rules()
+        ));
+    }
+    let tablebase = Arc::new(tablebase);
+    *loaded = Some((path.to_string(), tablebase.clone()));
+    Ok(Some(tablebase))
+}
+
+/// The notation of card names of `--notation`.
+pub fn cjul_notation(m: &Matches) -> Result<cjul::Notation, String> {
+    match m.value("--notation") {
+        Some(notation) => notation.parse(),
+        None => Ok(cjul::Notation::default()),
+    }
+}
+
+pub fn cjul_heuristic(m: &Matches) -> Result<cjul::Heuristic, String> {
+    match m.value("--heuristic") {
+        Some(path) => {
+            let profile = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            profile.parse().map_err(|e| format!("{}: {}", path, e))
+        }
+        None => Ok(cjul::Heuristic::default()),
+    }
+}
+
+fn verify<S: Solver>(input: &str, solution: &str, _: &Matches) -> Result<String, String> {
+    let puzzle = S::parse(input).map_err(|e| format!("invalid puzzle: {}", e))?;
+    puzzle.verify(solution)
+}
+
+impl Game {
+    fn names(&self) -> impl Iterator<Item = &'static str> {
+        std::iter::once(self.info.name).chain(self.info.aliases.iter().copied())
+    }
+}
+
+/// Find a game by name, alias, or an unambiguous prefix of them.
+pub fn find(name: &str) -> Result<&'static Game, String> {
+    let name = name.to_ascii_lowercase();
+    if let Some(game) = GAMES.iter().find(|g| g.names().any(|n| n == name)) {
+        return Ok(game);
+    }
+    let candidates: Vec<&Game> = GAMES
+        .iter()
+        .filter(|g| !name.is_empty() && g.names().any(|n| n.starts_with(&name)))
+        .collect();
+    match candidates[..] {
+        [game] => Ok(game),
+        [] => Err(format!(
+            "unsupported game: {:?}. Supported games: {}",
+            name,
+            names_str(GAMES.iter())
+        )),
+        _ => Err(format!(
+            "ambiguous game: {:?} could be {}",
+            name,
+            names_str(candidates.into_iter())
+        )),
+    }
+}
+
+fn names_str<'a>(games: impl Iterator<Item = &'a Game>) -> String {
+    games.map(|g| g.info.name).collect::<Vec<_>>().join(", ")
+}
+
+/// Render the game list.
+pub fn list() -> String {
+    let mut out = String::new();
+    for game in GAMES {
+        let info = game.info;
+        out += &format!("{:<10} {}\n", info.name, info.description);
+        if !info.aliases.is_empty() {
+            out += &format!("{:<10} Aliases: {}\n", "", info.aliases.join(", "));
+        }
+    }
+    out
+}
+
+#[test]
+fn test_find() {
+    let name = |s: &str| find(s).map(|g| g.info.name);
+    assert_eq!(name("cjul"), Ok("cjul"));
+    assert_eq!(name("cluj"), Ok("cjul"));
+    assert_eq!(name("CJ"), Ok("cjul"));
+    assert_eq!(name("cr"), Ok("cribbage"));
+    assert_eq!(name("d"), Ok("dungeons"));
+    assert!(name("c").unwrap_err().contains("ambiguous"));
+    assert!(name("foo").unwrap_err().contains("unsupported"));
+    assert!(name("").is_err());
+}
@@ -0,0 +1,206 @@
+//! Fit the weights of the cjul search order over a set of deals.
+//!
+//! Coordinate descent: change one weight at a time by one step, and keep
+//! the change while it lowers the total cost over all deals.
+
+use std::fs;
+
+use zachtronics::cjul;
+use zachtronics::limits::Limits;
+use zachtronics::Solution;
+use zachtronics::Solver;
+
+use crate::batch;
+use crate::cli::Flag;
+use crate::cli::Matches;
+use crate::registry;
+
+pub const FLAGS: &[Flag] = &[
+    Flag {
+        name: "--deals",
+        short: None,
+        value: Some("N"),
+        env: None,
+        help: "Also tune on N random deals. Default: 20",
+    },
+    Flag {
+        name: "--seed",
+        short: None,
+        value: Some("N"),
+        env: None,
+        help: "Seed of the first random deal. Default: 1",
+    },
+    Flag {
+        name: "--objective",
+        short: None,
+        value: Some("states|steps"),
+        env: None,
+        help: "Minimize searched states, or steps of the solutions. Default: states",
+    },
+    Flag {
+        name: "--rounds",
+        short: None,
+        value: Some("N"),
+        env: None,
+        help: "Stop after N passes over the weights. Default: until no change helps",
+    },
+    Flag {
+        name: "--heuristic",
+        short: None,
+        value: Some("FILE"),
+        env: None,
+        help: "Profile to start from. Default: the built-in weights",
+    },
+    Flag {
+        name: "--rules",
+        short: None,
+        value: Some("SPEC"),
+        env: None,
+        help: "Rule variant of the deals",
+    },
+    Flag {
+        name: "--output",
+        short: Some("-o"),
+        value: Some("FILE"),
+        env: None,
+        help: "Write the profile to FILE instead of stdout",
+    },
+    batch::JOBS_FLAG,
+];
+
+/// States searched per deal unless --max-states is given. Every candidate
+/// weight solves all deals again, so this is lower than for `stats`. A deal
+/// that reaches it costs the cap, which still ranks the candidates.
+const DEFAULT_MAX_STATES: usize = 100_000;
+
+/// Cost of a deal not solved, with `--objective steps`.
+const UNSOLVED_STEPS: usize = 1000;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Objective {
+    States,
+    Steps,
+}
+
+struct Tuner {
+    deals: Vec<cjul::State>,
+    rules: cjul::Rules,
+    objective: Objective,
+    limits: Limits,
+    jobs: usize,
+}
+
+impl Tuner {
+    /// Total cost of all deals. Lower is better.
+    fn cost(&self, heuristic: &cjul::Heuristic) -> usize {
+        let options = cjul::Options {
+            rules: self.rules.clone(),
+            heuristic: heuristic.clone(),
+            ..Default::default()
+        };
+        let costs = batch::run_parallel(self.jobs, self.deals.len(), |i| {
+            let solution = self.deals[i].solve_with_limits(&options, &self.limits);
+            match self.objective {
+                Objective::States => solution.stats().searched,
+                Objective::Steps => solution.step_count().unwrap_or(UNSOLVED_STEPS),
+            }
+        });
+        costs.iter().sum()
+    }
+}
+
+/// Run the tune command. `m.positional` are paths of deals.
+pub fn run(m: &Matches) -> Result<(), String> {
+    let objective = match m.value("--objective") {
+        None | Some("states") => Objective::States,
+        Some("steps") => Objective::Steps,
+        Some(v) => return Err(format!("unsupported objective: {}", v)),
+    };
+    let jobs = batch::jobs(m)?;
+    let rules = registry::cjul_rules(m)?;
+    let mut deals = Vec::new();
+    for path in batch::expand_paths(&m.positional)? {
+        let input = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let deal = cjul::State::parse_with_rules(&input, &rules)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        deals.push(deal);
+    }
+    let seed: u64 = m.parse_value("--seed")?.unwrap_or(1);
+    for i in 0..m.parse_value::<u64>("--deals")?.unwrap_or(20) {
+        let input = cjul::deal(seed + i, &rules, cjul::Notation::Latin);
+        deals.push(cjul::State::parse_with_rules(&input, &rules).map_err(|e| e.to_string())?);
+    }
+    if deals.is_empty() {
+        return Err("no deals to tune on".to_string());
+    }
+    let mut limits = registry::limits(m)?;
+    limits.max_states = limits.max_states.or(Some(DEFAULT_MAX_STATES));
+    let tuner = Tuner {
+        deals,
+        rules,
+        objective,
+        limits,
+        jobs,
+    };
+    let unit = match objective {
+        Objective::States => "states",
+        Objective::Steps => "steps",
+    };
+
+    let mut best = registry::cjul_heuristic(m)?;
+    let start_cost = tuner.cost(&best);
+    let mut best_cost = start_cost;
+    eprintln!("Start: {} {}.", best_cost, unit);
+    let rounds = m.parse_value::<usize>("--rounds")?.unwrap_or(usize::MAX);
+    for round in 1..=rounds {
+        let mut improved = false;
+        for (name, _) in best.weights() {
+            for delta in [1, -1] {
+                // Keep going in a direction while it helps.
+                loop {
+                    let mut candidate = best.clone();
+                    let weight = candidate.weight_mut(name).unwrap();
+                    *weight = match weight.checked_add_signed(delta) {
+                        Some(v) => v,
+                        None => break,
+                    };
+                    if candidate.validate().is_err() {
+                        break;
+                    }
+                    let cost = tuner.cost(&candidate);
+                    if cost >= best_cost {
+                        break;
+                    }
+                    eprintln!(
+                        "Round {}: {} = {}: {} {}.",
+                        round,
+                        name,
+                        candidate.weight_mut(name).unwrap(),
+                        cost,
+                        unit
+                    );
+                    best = candidate;
+                    best_cost = cost;
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    let profile = format!(
+        "# {} deals. {} {} (was {}).\n{}",
+        tuner.deals.len(),
+        best_cost,
+        unit,
+        start_cost,
+        best
+    );
+    match m.value("--output") {
+        Some(path) => fs::write(path, profile).map_err(|e| format!("{}: {}", path, e))?,
+        None => print!("{}", profile),
+    }
+    Ok(())
+}
@@ -25,26 +25,17 @@ pub fn cached_bool_env(cached: &AtomicUsize, env_name: &'static str) -> bool {
     }
 }
 
-/// Whether run under debug mode.
-pub fn is_debug() -> bool {
-    static DEBUG: AtomicUsize = AtomicUsize::new(0);
-    cached_bool_env(&DEBUG, "D")
-}
-
 /// Whether run under verbose mode.
+/// Falls back to the (deprecated) `V` environment variable if not set.
 pub fn is_verbose() -> bool {
-    static VERBOSE: AtomicUsize = AtomicUsize::new(0);
     cached_bool_env(&VERBOSE, "V")
 }
 
-#[macro_export]
-macro_rules! dprintln {
-    ($($t:tt)*) => {
-        if crate::util::is_debug() {
-            eprintln!($($t)*);
-        }
-    }
+/// Turn on or off verbose mode.
+pub fn set_verbose(value: bool) {
+    VERBOSE.store(2 + value as usize, Ordering::Relaxed);
 }
+
 #[macro_export]
 macro_rules! or {
     ($e:expr, $($s:tt)*) => {
@@ -101,6 +92,12 @@ impl<T, const N: usize> AsRef<[T]> for NVec<T, N> {
     }
 }
 
+impl<T, const N: usize> AsMut<[T]> for NVec<T, N> {
+    fn as_mut(&mut self) -> &mut [T] {
+        &mut self.inner[..self.len as usize]
+    }
+}
+
 impl<T, const N: usize> NVec<T, N> {
     pub fn len(&self) -> u8 {
         self.len
@@ -136,3 +133,33 @@ impl<T: Copy + Default, const N: usize> NVec<T, N> {
         }
     }
 }
+
+/// Small seeded random number generator (SplitMix64). Not for cryptography.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`. `n` must not be 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Fisher-Yates shuffle.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}